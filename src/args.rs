@@ -4,6 +4,8 @@ use anyhow::{Context as _, Error, Result};
 use clap::{Parser, Subcommand, ValueEnum};
 
 use crate::config::GitlabConfig;
+#[cfg(feature = "sqlite")]
+use crate::history::Aggregate;
 
 pub fn parse() -> Args {
     Args::parse()
@@ -19,16 +21,60 @@ pub struct Args {
 
 #[derive(Debug, Subcommand)]
 pub enum Command {
+    Cache {
+        #[arg(long)]
+        root: Option<PathBuf>,
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    #[command(allow_missing_positional = true)]
     Compare {
-        baseline: PathBuf,
+        #[cfg_attr(
+            feature = "sqlite",
+            arg(required_unless_present = "aggregate", conflicts_with = "aggregate")
+        )]
+        #[cfg_attr(not(feature = "sqlite"), arg(required = true))]
+        baseline: Option<PathBuf>,
         test: PathBuf,
         #[command(flatten)]
         compare_args: CompareArgs,
+        #[cfg(feature = "sqlite")]
+        #[command(flatten)]
+        baseline_history: BaselineHistory,
     },
     Generate {
+        /// Store the generated values in the local cache
         #[arg(long)]
         cache: bool,
+        /// Store the generated values in all storage backends that support it, by default the
+        /// local cache and Git notes
+        #[arg(long, alias = "notes")]
+        store: bool,
+        /// Fail if any metric could not be generated
+        #[arg(long)]
+        strict: bool,
         root: Option<PathBuf>,
+        #[command(flatten)]
+        gitlab: Gitlab,
+    },
+    #[cfg(feature = "sqlite")]
+    History {
+        root: Option<PathBuf>,
+        #[arg(long)]
+        database: Option<PathBuf>,
+        #[arg(long)]
+        branch: Option<String>,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        #[arg(long, value_enum)]
+        aggregate: Option<Aggregate>,
+        #[arg(long)]
+        metric: Option<String>,
+    },
+    List {
+        root: Option<PathBuf>,
+        #[command(flatten)]
+        gitlab: Gitlab,
     },
     Load {
         root: Option<PathBuf>,
@@ -36,6 +82,13 @@ pub enum Command {
         rev: Rev,
         #[command(flatten)]
         gitlab: Gitlab,
+        /// Fall back to up to N ancestors of the revision if it has no values
+        #[arg(long, default_value_t = 0)]
+        fallback_ancestors: usize,
+    },
+    Merge {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
     },
     Run {
         root: Option<PathBuf>,
@@ -43,28 +96,88 @@ pub enum Command {
         rev: Rev,
         #[command(flatten)]
         gitlab: Gitlab,
+        /// Fall back to up to N ancestors of the baseline commit if it has no values
+        #[arg(long, default_value_t = 0)]
+        fallback_ancestors: usize,
         #[command(flatten)]
         compare_args: CompareArgs,
+        /// Store the generated values in the local cache
         #[arg(long)]
         cache: bool,
+        /// Store the generated values in all storage backends that support it, by default the
+        /// local cache and Git notes
+        #[arg(long, alias = "notes")]
+        store: bool,
+        /// Regenerate baseline values in a temporary worktree if metric definitions have changed
+        #[arg(long)]
+        refresh_stale: bool,
+        /// Generate the baseline values in a temporary worktree if they are not available
+        #[arg(long)]
+        generate_baseline: bool,
+        #[cfg(feature = "sqlite")]
+        #[command(flatten)]
+        baseline_history: BaselineHistory,
+    },
+    FetchNotes {
+        root: Option<PathBuf>,
+        #[arg(long, default_value = "origin")]
+        remote: String,
+    },
+    PushNotes {
+        root: Option<PathBuf>,
+        #[arg(long, default_value = "origin")]
+        remote: String,
     },
 }
 
 impl Command {
     pub fn root(&self) -> Option<&Path> {
         match self {
+            Self::Cache { root, .. } => root.as_deref(),
             Self::Compare { .. } => None,
             Self::Generate { root, .. } => root.as_deref(),
+            #[cfg(feature = "sqlite")]
+            Self::History { root, .. } => root.as_deref(),
+            Self::List { root, .. } => root.as_deref(),
             Self::Load { root, .. } => root.as_deref(),
+            Self::Merge { .. } => None,
             Self::Run { root, .. } => root.as_deref(),
+            Self::FetchNotes { root, .. } => root.as_deref(),
+            Self::PushNotes { root, .. } => root.as_deref(),
         }
     }
 }
 
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    List,
+    Show {
+        rev: String,
+    },
+    Prune {
+        /// Remove entries for commits older than the given number of days
+        #[arg(long)]
+        older_than: Option<u32>,
+        /// Keep only the given number of entries for the newest commits
+        #[arg(long)]
+        keep: Option<usize>,
+        /// Remove entries for commits that are not reachable from any branch
+        #[arg(long)]
+        unreachable: bool,
+    },
+    Clear,
+}
+
 #[derive(Debug, clap::Args)]
 pub struct CompareArgs {
     #[arg(long, default_value_t, value_enum)]
     pub output_format: OutputFormat,
+    /// Also compare values that are not defined in the configuration
+    #[arg(long)]
+    pub all_values: bool,
+    /// Fail if any metric could not be generated
+    #[arg(long)]
+    pub strict: bool,
 }
 
 #[derive(Clone, Copy, Debug, Default, ValueEnum)]
@@ -74,6 +187,20 @@ pub enum OutputFormat {
     Markdown,
 }
 
+/// Use an aggregate of the values in the history database as the baseline
+#[cfg(feature = "sqlite")]
+#[derive(Debug, clap::Args)]
+pub struct BaselineHistory {
+    #[arg(long = "baseline-history", value_enum)]
+    pub aggregate: Option<Aggregate>,
+    #[arg(long = "baseline-history-branch")]
+    pub branch: Option<String>,
+    #[arg(long = "baseline-history-limit", default_value_t = 10)]
+    pub limit: usize,
+    #[arg(long = "baseline-history-database")]
+    pub database: Option<PathBuf>,
+}
+
 #[derive(Debug, clap::Args)]
 #[group(multiple = false)]
 pub struct Rev {
@@ -93,6 +220,12 @@ pub struct Gitlab {
     pub job: Option<String>,
     #[arg(long = "gitlab-artifact")]
     pub artifact: Option<String>,
+    #[arg(long = "gitlab-package")]
+    pub package: Option<String>,
+    #[arg(long = "gitlab-ref")]
+    pub ref_: Option<String>,
+    #[arg(long = "gitlab-source")]
+    pub source: Option<String>,
 }
 
 impl Gitlab {
@@ -101,6 +234,9 @@ impl Gitlab {
             || self.project.is_some()
             || self.job.is_some()
             || self.artifact.is_some()
+            || self.package.is_some()
+            || self.ref_.is_some()
+            || self.source.is_some()
     }
 
     pub fn into_config(self) -> Result<Option<GitlabConfig>> {
@@ -125,6 +261,21 @@ impl TryFrom<Gitlab> for GitlabConfig {
             project,
             job,
             artifact,
+            package: gitlab.package,
+            ref_: gitlab.ref_,
+            source: gitlab.source,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory as _;
+
+    use super::Args;
+
+    #[test]
+    fn verify_args() {
+        Args::command().debug_assert();
+    }
+}
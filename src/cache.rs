@@ -1,42 +1,107 @@
-use std::{fs, path::Path, process::Command};
+use std::{
+    fs, iter,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Context as _, Result};
-use log::{debug, error, info};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
 
-pub fn store(path: &Path, data: &str) -> Result<()> {
-    if !is_git_repo(path) {
-        debug!("Root is not a Git repository, not caching metrics");
-        return Ok(());
+use crate::{
+    data, directory, git,
+    storage::{Backend, Loaded},
+};
+
+/// The local cache in the `.repometrics` directory of the repository.
+pub struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_owned(),
+        }
     }
-    if !is_clean(path) {
-        debug!("Git repository is not in a clean state, not caching metrics");
-        return Ok(());
+}
+
+impl Backend for Cache {
+    fn name(&self) -> String {
+        format!("cache in '{}'", self.root.display())
     }
-    let commit = get_commit(path)?;
-    store_for_rev(path, &commit, data)
+
+    fn load(&self, commit: &str) -> Result<Option<Loaded>> {
+        load(&self.root, commit).map(|data| data.map(From::from))
+    }
+
+    fn store(&self, commit: &str, data: &str) -> Result<()> {
+        store_for_rev(&self.root, commit, data)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        list(&self.root)
+    }
+
+    fn is_cache(&self) -> bool {
+        true
+    }
+}
+
+/// Separates the commit from the hash of the uncommitted changes in cache keys for dirty
+/// working trees.
+const DIRTY_SEPARATOR: &str = "-dirty-";
+
+pub fn store(path: &Path, data: &str) -> Result<()> {
+    let Some(key) = current_key(path)? else {
+        return Ok(());
+    };
+    store_for_rev(path, &key, data)
 }
 
+/// Stores the values for a commit in the cache.
+///
+/// If the cache already contains values for the commit, for example from a parallel job that
+/// generated other metrics, the new values are merged into the existing ones.  As the stored
+/// values can come from jobs with different configurations, values of metrics that are removed
+/// from the configuration are kept until the entry is removed with `cache prune` or
+/// `cache clear`.
 pub fn store_for_rev(path: &Path, rev: &str, data: &str) -> Result<()> {
     let cache_dir = path.join(".repometrics");
-    if !cache_dir.exists() {
-        fs::create_dir(&cache_dir).with_context(|| {
-            format!("failed to create cache directory '{}'", cache_dir.display())
-        })?;
-    }
+    fs::create_dir_all(&cache_dir)
+        .with_context(|| format!("failed to create cache directory '{}'", cache_dir.display()))?;
     let gitignore = cache_dir.join(".gitignore");
     if !gitignore.exists() {
         fs::write(&gitignore, "*").with_context(|| {
             format!("failed to create gitignore file '{}'", gitignore.display())
         })?;
     }
+    let _lock = directory::lock(&cache_dir)?;
     let cache_file = cache_dir.join(format!("{}.toml", rev));
+    let data = if cache_file.exists() {
+        merge(&cache_file, data)?
+    } else {
+        data.to_owned()
+    };
     info!("Writing data to cache file '{}'", cache_file.display());
-    fs::write(&cache_file, data)
-        .with_context(|| format!("failed to write cache file '{}'", cache_file.display()))
+    directory::write_atomic(&cache_file, &data)
+}
+
+fn merge(cache_file: &Path, data: &str) -> Result<String> {
+    let mut values = match data::Values::load(cache_file) {
+        Ok(values) => values,
+        Err(err) => {
+            warn!("Overwriting invalid cache file: {err:#}");
+            return Ok(data.to_owned());
+        }
+    };
+    debug!("Merging values into cache file '{}'", cache_file.display());
+    let new_values = toml::from_str(data).context("failed to parse values")?;
+    values.update(new_values);
+    values.format()
 }
 
 pub fn load(path: &Path, rev: &str) -> Result<Option<String>> {
-    let cache_file = path.join(format!(".repometrics/{}.toml", rev));
+    let cache_file = cache_file(path, rev);
     if cache_file.exists() {
         info!("Reading cache file '{}'", cache_file.display());
         fs::read_to_string(&cache_file)
@@ -47,106 +112,263 @@ pub fn load(path: &Path, rev: &str) -> Result<Option<String>> {
     }
 }
 
+pub fn list(path: &Path) -> Result<Vec<String>> {
+    directory::list_values_files(&path.join(".repometrics"))
+}
+
+/// A values file in the cache.
+pub struct Entry {
+    pub commit: String,
+    pub time: Option<DateTime<Utc>>,
+    pub metrics: Option<usize>,
+}
+
+/// Returns the cache entries sorted by commit time, starting with the newest commit.
+///
+/// The commit time is `None` if the commit is not part of the repository and the number of
+/// metrics is `None` if the values file could not be parsed.
+pub fn entries(path: &Path) -> Result<Vec<Entry>> {
+    let repo = git::Repository::discover(path).ok();
+    let mut entries = Vec::new();
+    for commit in list(path)? {
+        let (rev, _) = split_key(&commit);
+        let time = repo.as_ref().and_then(|repo| repo.commit_time(rev));
+        let metrics = match data::Values::load(cache_file(path, &commit)) {
+            Ok(values) => Some(values.len()),
+            Err(err) => {
+                warn!("Failed to load cache entry for commit {commit}: {err:#}");
+                None
+            }
+        };
+        entries.push(Entry {
+            commit,
+            time,
+            metrics,
+        });
+    }
+    entries.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| a.commit.cmp(&b.commit)));
+    Ok(entries)
+}
+
+pub fn remove(path: &Path, rev: &str) -> Result<()> {
+    let cache_file = cache_file(path, rev);
+    info!("Removing cache file '{}'", cache_file.display());
+    fs::remove_file(&cache_file)
+        .with_context(|| format!("failed to remove cache file '{}'", cache_file.display()))
+}
+
+/// Removes the cache entries that are older than `older_than`, exceed the `keep` newest entries
+/// or, if `unreachable` is set, are not reachable from any branch, and returns their commits.
+pub fn prune(
+    path: &Path,
+    older_than: Option<DateTime<Utc>>,
+    keep: Option<usize>,
+    unreachable: bool,
+) -> Result<Vec<String>> {
+    let repo = if unreachable {
+        Some(git::Repository::discover(path)?)
+    } else {
+        None
+    };
+    let mut removed = Vec::new();
+    for (i, entry) in entries(path)?.into_iter().enumerate() {
+        let remove = keep.is_some_and(|keep| i >= keep)
+            || older_than.is_some_and(|cutoff| entry.time.is_some_and(|time| time < cutoff))
+            || repo
+                .as_ref()
+                .map(|repo| {
+                    let (rev, _) = split_key(&entry.commit);
+                    repo.is_reachable(rev).map(|reachable| !reachable)
+                })
+                .transpose()?
+                .unwrap_or_default();
+        if remove {
+            self::remove(path, &entry.commit)?;
+            removed.push(entry.commit);
+        }
+    }
+    Ok(removed)
+}
+
+pub fn clear(path: &Path) -> Result<()> {
+    let cache_dir = path.join(".repometrics");
+    if cache_dir.exists() {
+        info!("Removing cache directory '{}'", cache_dir.display());
+        fs::remove_dir_all(&cache_dir).with_context(|| {
+            format!("failed to remove cache directory '{}'", cache_dir.display())
+        })?;
+    }
+    Ok(())
+}
+
+fn cache_file(path: &Path, rev: &str) -> PathBuf {
+    path.join(format!(".repometrics/{}.toml", rev))
+}
+
+/// Returns the current commit if the repository is in a clean state.
+pub fn current_commit(path: &Path) -> Result<Option<String>> {
+    let repo = match git::Repository::discover(path) {
+        Ok(repo) => repo,
+        Err(git::Error::NotFound(_)) => {
+            debug!("Root is not a Git repository, not storing metrics");
+            return Ok(None);
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if !repo.is_clean()? {
+        debug!("Git repository is not in a clean state, not storing metrics");
+        return Ok(None);
+    }
+    Ok(Some(repo.head_commit()?))
+}
+
+/// Returns the cache key for the current state of the working tree.
+///
+/// For a clean working tree, this is the current commit.  Otherwise, a hash of the uncommitted
+/// changes is appended so that intermediate states can be cached and compared.
+pub fn current_key(path: &Path) -> Result<Option<String>> {
+    let repo = match git::Repository::discover(path) {
+        Ok(repo) => repo,
+        Err(git::Error::NotFound(_)) => {
+            debug!("Root is not a Git repository, not caching metrics");
+            return Ok(None);
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let commit = repo.head_commit()?;
+    if let Some(hash) = repo.diff_hash()? {
+        Ok(Some(format!("{commit}{DIRTY_SEPARATOR}{hash}")))
+    } else {
+        Ok(Some(commit))
+    }
+}
+
+/// Splits a cache key into the commit and the hash of the uncommitted changes, if any.
+pub fn split_key(key: &str) -> (&str, Option<&str>) {
+    match key.split_once(DIRTY_SEPARATOR) {
+        Some((commit, hash)) => (commit, Some(hash)),
+        None => (key, None),
+    }
+}
+
 pub fn get_rev(path: &Path, rev: Option<&str>, base: Option<&str>) -> Result<String> {
-    anyhow::ensure!(
-        is_git_repo(path),
-        "Directory '{}' is not a Git repository",
-        path.display(),
-    );
-    match (rev, base) {
-        (Some(rev), _) => resolve_commit(path, rev),
-        (_, Some(base)) => merge_base(path, base),
+    let repo = git::Repository::discover(path)?;
+    let commit = match (rev, base) {
+        (Some(rev), _) => match split_key(rev) {
+            (rev, Some(hash)) => format!("{}{DIRTY_SEPARATOR}{hash}", repo.resolve_commit(rev)?),
+            (rev, None) => repo.resolve_commit(rev)?,
+        },
+        (_, Some(base)) => repo.merge_base(base)?,
         (None, None) => {
-            anyhow::ensure!(
-                is_clean(path),
-                "Git repository '{}' is not in a clean state",
-                path.display(),
-            );
-            get_commit(path)
+            if !repo.is_clean()? {
+                info!("Git repository is not in a clean state, using HEAD");
+            }
+            repo.head_commit()?
         }
+    };
+    Ok(commit)
+}
+
+/// Returns the commit and up to `n` of its first-parent ancestors, starting with the commit.
+///
+/// If `rev` is a cache key for a dirty working tree, it is returned before the commit itself.
+pub fn ancestors(path: &Path, rev: &str, n: usize) -> Result<Vec<String>> {
+    let repo = git::Repository::discover(path)?;
+    match split_key(rev) {
+        (commit, Some(_)) => Ok(iter::once(rev.to_owned())
+            .chain(repo.ancestors(commit, n.saturating_sub(1))?)
+            .collect()),
+        (commit, None) => Ok(repo.ancestors(commit, n)?),
     }
 }
 
-fn is_git_repo(path: &Path) -> bool {
-    path.join(".git").is_dir()
+/// Returns the current branch, preferring the branch set by the CI system.
+#[cfg(feature = "sqlite")]
+pub fn get_branch(path: &Path) -> Option<String> {
+    for var in ["CI_COMMIT_REF_NAME", "GITHUB_HEAD_REF", "GITHUB_REF_NAME"] {
+        if let Ok(branch) = std::env::var(var) {
+            if !branch.is_empty() {
+                return Some(branch);
+            }
+        }
+    }
+    git::Repository::discover(path).ok()?.branch()
 }
 
-fn is_clean(path: &Path) -> bool {
-    let result = Command::new("git")
-        .current_dir(path)
-        .arg("status")
-        .arg("--porcelain")
-        .output();
-    let output = match result {
-        Ok(output) => output,
-        Err(err) => {
-            error!("Could not run git status: {err}");
-            return false;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_repo;
+
+    const VALUES: &str = "version = \"1\"\n\n[values]\na = 1\n";
+
+    #[test]
+    fn prune_entries() {
+        let (dir, commits) = test_repo(&[1000, 2000, 3000]);
+        let path = dir.path();
+        let dirty = format!("{}{DIRTY_SEPARATOR}abcd", commits[2]);
+        let unknown = "1".repeat(40);
+        for key in commits.iter().chain([&dirty, &unknown]) {
+            store_for_rev(path, key, VALUES).unwrap();
         }
-    };
-    if !output.status.success() {
-        error!(
-            "Running git status failed with status code {} in '{}'",
-            output.status,
-            path.display()
-        );
-        return false;
-    }
-    output.stdout.is_empty()
-}
-
-fn get_commit(path: &Path) -> Result<String> {
-    let output = Command::new("git")
-        .current_dir(path)
-        .arg("rev-parse")
-        .arg("HEAD")
-        .output()
-        .context("failed to run git rev-parse")?;
-    anyhow::ensure!(
-        output.status.success(),
-        "running git rev-parse failed with status code {} in '{}'",
-        output.status,
-        path.display()
-    );
-    String::from_utf8(output.stdout)
-        .map(|s| s.trim().to_owned())
-        .context("failed to decode git revision as UTF-8")
-}
-
-fn resolve_commit(path: &Path, rev: &str) -> Result<String> {
-    let output = Command::new("git")
-        .current_dir(path)
-        .arg("rev-list")
-        .arg("-1")
-        .arg(rev)
-        .output()
-        .context("failed to run git rev-list")?;
-    anyhow::ensure!(
-        output.status.success(),
-        "running git rev-list failed with status code {} in '{}'",
-        output.status,
-        path.display()
-    );
-    String::from_utf8(output.stdout)
-        .map(|s| s.trim().to_owned())
-        .context("failed to decode git revision as UTF-8")
-}
-
-fn merge_base(path: &Path, base: &str) -> Result<String> {
-    let output = Command::new("git")
-        .current_dir(path)
-        .arg("merge-base")
-        .arg(base)
-        .arg("HEAD")
-        .output()
-        .context("failed to run git merge-base")?;
-    anyhow::ensure!(
-        output.status.success(),
-        "running git merge-base list failed with status code {} in '{}'",
-        output.status,
-        path.display()
-    );
-    String::from_utf8(output.stdout)
-        .map(|s| s.trim().to_owned())
-        .context("failed to decode git revision as UTF-8")
+
+        // entries without a commit time are never too old
+        let cutoff = DateTime::from_timestamp(2500, 0);
+        let removed = prune(path, cutoff, None, false).unwrap();
+        assert_eq!(removed, [commits[1].clone(), commits[0].clone()]);
+
+        // dirty entries are reachable if their commit is reachable
+        let removed = prune(path, None, None, true).unwrap();
+        assert_eq!(removed, [unknown]);
+
+        let removed = prune(path, None, Some(1), false).unwrap();
+        assert_eq!(removed, [dirty]);
+        assert_eq!(list(path).unwrap(), [commits[2].clone()]);
+    }
+
+    #[test]
+    fn concurrent_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let writers: Vec<_> = (0..8)
+            .map(|i| {
+                let path = dir.path().to_owned();
+                std::thread::spawn(move || {
+                    let data = format!(
+                        "version = \"1\"\n\n[values]\nm{i} = {i}\n\n[fingerprints]\nm{i} = \"f{i}\"\n"
+                    );
+                    store_for_rev(&path, "c1", &data).unwrap();
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let values = data::Values::load(cache_file(dir.path(), "c1")).unwrap();
+        assert_eq!(values.len(), 8);
+        for i in 0..8 {
+            assert_eq!(values.get(&format!("m{i}")), Some(i));
+        }
+    }
+
+    #[test]
+    fn dirty_keys() {
+        let (dir, commits) = test_repo(&[1000, 2000, 3000]);
+        let path = dir.path();
+        assert_eq!(current_key(path).unwrap().as_ref(), Some(&commits[2]));
+        assert_eq!(split_key(&commits[2]), (commits[2].as_str(), None));
+
+        fs::write(path.join("file"), "changed").unwrap();
+        let key = current_key(path).unwrap().unwrap();
+        let hash = git::Repository::discover(path)
+            .unwrap()
+            .diff_hash()
+            .unwrap()
+            .unwrap();
+        assert_eq!(split_key(&key), (commits[2].as_str(), Some(hash.as_str())));
+        assert_eq!(get_rev(path, Some(&key), None).unwrap(), key);
+
+        // the commit of a dirty key counts as its first ancestor
+        let ancestors = ancestors(path, &key, 2).unwrap();
+        assert_eq!(ancestors, [key, commits[2].clone(), commits[1].clone()]);
+    }
 }
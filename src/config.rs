@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
 };
@@ -7,7 +8,10 @@ use anyhow::{Context as _, Result};
 use log::debug;
 use serde::Deserialize;
 
-use crate::{data::Metrics, gitlab::Api};
+use crate::{
+    data::{Direction, Metrics},
+    github, gitlab,
+};
 
 const CONFIG_FILE_NAME: &str = "repometrics.toml";
 
@@ -40,8 +44,12 @@ pub struct Config {
     pub path: Option<PathBuf>,
     #[serde(default)]
     pub defaults: Defaults,
+    pub github: Option<GithubConfig>,
     pub gitlab: Option<GitlabConfig>,
     pub metrics: Option<Metrics>,
+    #[serde(default)]
+    pub prepare: PrepareConfig,
+    pub storage: Option<Vec<StorageConfig>>,
 }
 
 impl Config {
@@ -74,18 +82,108 @@ impl Config {
 #[derive(Debug, Default, Deserialize)]
 pub struct Defaults {
     pub significance_threshold: Option<f32>,
+    pub direction: Option<Direction>,
+    #[serde(default)]
+    pub fail_on_removed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum StorageConfig {
+    Cache,
+    Directory(DirectoryConfig),
+    Notes,
+    Github,
+    Gitlab,
+    Http(HttpConfig),
+    S3(S3Config),
+    #[cfg(feature = "sqlite")]
+    Sqlite(SqliteConfig),
+}
+
+/// Commands that are executed before generating the metrics.
+#[derive(Debug, Default, Deserialize)]
+pub struct PrepareConfig {
+    #[serde(default)]
+    pub commands: Vec<PrepareCommand>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrepareCommand {
+    pub command: Vec<String>,
+    pub dir: Option<PathBuf>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Deserialize)]
+pub struct DirectoryConfig {
+    pub path: PathBuf,
+    pub project: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct HttpConfig {
+    pub url: String,
+    pub project: Option<String>,
+    pub token_env: Option<String>,
+    #[serde(default)]
+    pub upload: bool,
+}
+
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Deserialize)]
+pub struct SqliteConfig {
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    pub prefix: Option<String>,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_owned()
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct GitlabConfig {
     pub host: String,
     pub project: String,
     pub job: String,
     pub artifact: String,
+    pub package: Option<String>,
+    #[serde(rename = "ref")]
+    pub ref_: Option<String>,
+    pub source: Option<String>,
 }
 
 impl GitlabConfig {
-    pub fn api(&self) -> Result<Api<'_>> {
-        Api::new(&self.host, &self.project, &self.job, &self.artifact)
+    pub fn api(&self) -> Result<gitlab::Api<'_>> {
+        gitlab::Api::new(self)
     }
 }
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GithubConfig {
+    #[serde(default = "default_github_api")]
+    pub api: String,
+    pub repository: String,
+    pub workflow: Option<String>,
+    pub artifact: String,
+    pub file: String,
+}
+
+impl GithubConfig {
+    pub fn api(&self) -> Result<github::Api<'_>> {
+        github::Api::new(self)
+    }
+}
+
+fn default_github_api() -> String {
+    "https://api.github.com".to_owned()
+}
@@ -1,90 +1,604 @@
 use std::{
-    collections::BTreeMap,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
     fmt::{self, Display, Formatter, Write as _},
-    fs,
+    fs, iter,
     ops::Deref,
     path::Path,
+    process::Command,
 };
 
 use anyhow::{Context as _, Result};
-use serde::{Deserialize, Serialize};
+use log::{debug, error};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use sha2::{Digest as _, Sha256};
 
 use crate::config::Defaults;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default)]
 pub struct Metrics(BTreeMap<String, Metric>);
 
 impl Metrics {
     pub fn generate(&self, root: &Path) -> Values {
         let mut values = ValuesV1::default();
         for (id, metric) in &self.0 {
-            if let Some(value) = metric.generate(root) {
-                values.values.insert(id.to_owned(), value);
+            match metric.generate(id, root) {
+                Ok(metric_values) => values.values.extend(metric_values),
+                Err(err) => {
+                    error!("Failed to generate metric {id}: {err:#}");
+                    values.errors.insert(id.clone(), format!("{err:#}"));
+                }
             }
+            values
+                .fingerprints
+                .insert(id.clone(), metric.fingerprint.clone());
         }
         Values::V1(values)
     }
 
-    pub fn compare(&self, defaults: &Defaults, baseline: &Values, test: &Values) -> Comparisons {
+    /// Returns the IDs of the metrics whose definition differs from the definition that was used
+    /// to generate the values.
+    ///
+    /// Values without fingerprints, for example from older versions, are never considered stale.
+    pub fn stale(&self, values: &Values) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter(|(id, metric)| {
+                let old_ids = metric.old_ids(id, id);
+                values
+                    .fingerprint(&old_ids)
+                    .is_some_and(|fingerprint| fingerprint != metric.fingerprint)
+            })
+            .map(|(id, _)| id.as_str())
+            .collect()
+    }
+
+    /// Returns the metrics with the given IDs.
+    pub fn select(&self, ids: &[&str]) -> Self {
+        Self(
+            self.0
+                .iter()
+                .filter(|(id, _)| ids.contains(&id.as_str()))
+                .map(|(id, metric)| (id.clone(), metric.clone()))
+                .collect(),
+        )
+    }
+
+    /// Compares the values of the configured metrics.
+    ///
+    /// If `all` is set, values that are not produced by any of the configured metrics are also
+    /// compared using the default settings.
+    pub fn compare(
+        &self,
+        defaults: &Defaults,
+        baseline: &Values,
+        test: &Values,
+        all: bool,
+    ) -> Comparisons {
         let mut comparisons = Comparisons::default();
+        let stale: BTreeSet<_> = self
+            .stale(baseline)
+            .into_iter()
+            .chain(self.stale(test))
+            .collect();
+        let mut unconfigured: BTreeSet<_> = if all {
+            baseline.ids().chain(test.ids()).collect()
+        } else {
+            Default::default()
+        };
         for (id, metric) in &self.0 {
             let significance_threshold = metric
+                .settings
                 .significance_threshold
                 .or(defaults.significance_threshold);
+            let direction = metric
+                .settings
+                .direction
+                .or(defaults.direction)
+                .unwrap_or_default();
+            let old_ids = metric.old_ids(id, id);
+            let baseline_error = baseline.error(&old_ids);
+            let test_error = test.error(std::slice::from_ref(id));
+            if baseline_error.is_some() || test_error.is_some() {
+                comparisons.failed.push(Failure {
+                    metric: id.clone(),
+                    baseline: baseline_error.map(ToOwned::to_owned),
+                    test: test_error.map(ToOwned::to_owned),
+                });
+                // the remaining values of the metric are not comparable
+                unconfigured
+                    .retain(|value_id| !old_ids.iter().any(|old_id| is_value_of(value_id, old_id)));
+                continue;
+            }
+            for value_id in metric.value_ids(id, baseline, test) {
+                let old_ids = metric.old_ids(id, &value_id);
+                let mut old_value = baseline.get_any(&old_ids);
+                let mut new_value = test.get(&value_id);
+                if &value_id != id {
+                    // sub-metrics are only present if they are non-zero
+                    let parent_ids = metric.old_ids(id, id);
+                    old_value = old_value.or_else(|| baseline.get_any(&parent_ids).map(|_| 0));
+                    new_value = new_value.or_else(|| test.get(id).map(|_| 0));
+                }
+                unconfigured.remove(value_id.as_str());
+                for old_id in &old_ids {
+                    unconfigured.remove(old_id.as_str());
+                }
+                let mut comparison = Comparison::new(value_id, old_value, new_value);
+                comparison.group = metric.group.clone();
+                comparison.direction = direction;
+                if !stale.contains(id.as_str()) {
+                    comparisons.push(comparison, significance_threshold);
+                } else if comparison.old_value.is_some() || comparison.new_value.is_some() {
+                    comparisons.stale.push(comparison);
+                }
+            }
+        }
+        if all {
+            let configured: BTreeSet<_> = self
+                .0
+                .iter()
+                .flat_map(|(id, metric)| metric.old_ids(id, id))
+                .collect();
+            let failed: BTreeSet<_> = baseline
+                .errors()
+                .chain(test.errors())
+                .map(|(id, _)| id)
+                .filter(|id| !configured.contains(*id))
+                .collect();
+            for id in failed {
+                let ids = [id.to_owned()];
+                comparisons.failed.push(Failure {
+                    metric: id.to_owned(),
+                    baseline: baseline.error(&ids).map(ToOwned::to_owned),
+                    test: test.error(&ids).map(ToOwned::to_owned),
+                });
+                unconfigured.retain(|value_id| !is_value_of(value_id, id));
+            }
+        }
+        for id in unconfigured {
             let old_value = baseline.get(id);
             let new_value = test.get(id);
-            let comparison = Comparison::new(id.to_owned(), old_value, new_value);
-            let is_significant = significance_threshold
-                .zip(comparison.relative_change)
-                .map(|(threshold, change)| change.abs() >= threshold)
-                .unwrap_or(true);
-            if is_significant {
-                comparisons.significant.push(comparison);
+            let mut comparison = Comparison::new(id.to_owned(), old_value, new_value);
+            comparison.group = id.rsplit_once('.').map(|(group, _)| group.to_owned());
+            comparison.direction = defaults.direction.unwrap_or_default();
+            comparisons.push(comparison, defaults.significance_threshold);
+        }
+        comparisons
+    }
+
+    /// Adds the metrics and nested groups defined in `table` to `metrics`.
+    ///
+    /// Tables with a `type` key are metrics, all other tables are groups.  The remaining keys of
+    /// a group are settings that are used as defaults for all metrics in the group.  Metrics with
+    /// a `matrix` key are expanded into one metric per combination of the matrix variables.
+    fn insert_group(
+        metrics: &mut BTreeMap<String, Metric>,
+        group: Option<&str>,
+        table: toml::Table,
+        parent_settings: &Settings,
+    ) -> Result<(), toml::de::Error> {
+        let (children, settings): (toml::Table, toml::Table) =
+            table.into_iter().partition(|(_, value)| value.is_table());
+        if let Some(key) = settings
+            .keys()
+            .find(|key| !Settings::KEYS.contains(&key.as_str()))
+        {
+            let group = group
+                .map(|group| format!(" of group '{group}'"))
+                .unwrap_or_default();
+            return Err(toml::de::Error::custom(format!(
+                "unknown setting '{key}'{group}, expected one of: {}",
+                Settings::KEYS.join(", ")
+            )));
+        }
+        let settings = toml::Value::Table(settings)
+            .try_into::<Settings>()?
+            .or(parent_settings);
+        for (name, child) in children {
+            let toml::Value::Table(mut child) = child else {
+                unreachable!();
+            };
+            if !child.contains_key("type") {
+                let id = Self::id(group, &name);
+                Self::insert_group(metrics, Some(&id), child, &settings)?;
+            } else if let Some(matrix) = child.remove("matrix") {
+                let matrix: Matrix = matrix.try_into()?;
+                let template = toml::Value::Table(child.clone());
+                matrix.check(&name, &template).map_err(|err| {
+                    let id = Self::id(group, &name);
+                    toml::de::Error::custom(format!("invalid metric template '{id}': {err}"))
+                })?;
+                for variables in matrix.combinations() {
+                    let name = substitute(&name, &variables);
+                    let mut child = toml::Value::Table(child.clone());
+                    substitute_value(&mut child, &variables);
+                    Self::insert_metric(metrics, group, name, child, &settings)?;
+                }
             } else {
-                comparisons.insignificant.push(comparison);
+                let child = toml::Value::Table(child);
+                Self::insert_metric(metrics, group, name, child, &settings)?;
             }
         }
-        comparisons
+        Ok(())
+    }
+
+    fn insert_metric(
+        metrics: &mut BTreeMap<String, Metric>,
+        group: Option<&str>,
+        name: String,
+        value: toml::Value,
+        settings: &Settings,
+    ) -> Result<(), toml::de::Error> {
+        let id = Self::id(group, &name);
+        let fingerprint = fingerprint(&value)?;
+        let mut metric: Metric = value.try_into()?;
+        metric.fingerprint = fingerprint;
+        metric.settings = metric.settings.or(settings);
+        metric.group = group.map(ToOwned::to_owned);
+        if metrics.contains_key(&id) {
+            return Err(toml::de::Error::custom(format!(
+                "duplicate metric ID '{id}'"
+            )));
+        }
+        metrics.insert(id, metric);
+        Ok(())
+    }
+
+    fn id(group: Option<&str>, name: &str) -> String {
+        if let Some(group) = group {
+            format!("{group}.{name}")
+        } else {
+            name.to_owned()
+        }
     }
 }
 
-#[derive(Debug, Deserialize)]
+impl<'de> Deserialize<'de> for Metrics {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let table = toml::Table::deserialize(deserializer)?;
+        let mut metrics = BTreeMap::new();
+        Self::insert_group(&mut metrics, None, table, &Settings::default())
+            .map_err(D::Error::custom)?;
+        Ok(Self(metrics))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
 pub struct Metric {
     #[serde(flatten)]
     def: MetricDef,
+    #[serde(flatten)]
+    settings: Settings,
+    /// Previous IDs of this metric that are used to look up the baseline values.
+    #[serde(default)]
+    renamed_from: Vec<String>,
+    #[serde(skip)]
+    group: Option<String>,
+    #[serde(skip)]
+    fingerprint: String,
+}
+
+/// Returns a hash of the metric definition without the comparison settings.
+fn fingerprint(value: &toml::Value) -> Result<String, toml::de::Error> {
+    let mut value = value.clone();
+    if let toml::Value::Table(table) = &mut value {
+        for key in ["significance_threshold", "direction", "renamed_from"] {
+            table.remove(key);
+        }
+    }
+    let s = toml::to_string(&value).map_err(toml::de::Error::custom)?;
+    let hash = Sha256::digest(s.as_bytes());
+    Ok(hex::encode(&hash[..8]))
+}
+
+/// Variables for the expansion of a metric template, see [`Metrics::insert_group`].
+#[derive(Debug, Deserialize)]
+struct Matrix(BTreeMap<String, Vec<String>>);
+
+impl Matrix {
+    /// Checks that all variables have values and that the name and the definition of the
+    /// template only use placeholders for the variables of the matrix.
+    fn check(&self, name: &str, template: &toml::Value) -> Result<(), String> {
+        if self.0.is_empty() {
+            return Err("matrix has no variables".to_owned());
+        }
+        if let Some((variable, _)) = self.0.iter().find(|(_, values)| values.is_empty()) {
+            return Err(format!("matrix variable '{variable}' has no values"));
+        }
+        let mut strings = vec![name];
+        collect_strings(template, &mut strings);
+        for s in strings {
+            if let Some(placeholder) = placeholders(s).find(|p| !self.0.contains_key(*p)) {
+                return Err(format!("unknown placeholder '{{{placeholder}}}'"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns all combinations of the variable values.
+    fn combinations(&self) -> Vec<Vec<(&str, &str)>> {
+        let mut combinations = vec![Vec::new()];
+        for (variable, values) in &self.0 {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push((variable.as_str(), value.as_str()));
+                        combination
+                    })
+                })
+                .collect();
+        }
+        combinations
+    }
+}
+
+/// Replaces all `{variable}` placeholders in `s` with the value of the variable.
+fn substitute(s: &str, variables: &[(&str, &str)]) -> String {
+    variables.iter().fold(s.to_owned(), |s, (variable, value)| {
+        s.replace(&format!("{{{variable}}}"), value)
+    })
+}
+
+/// Returns the names of all `{variable}` placeholders in `s`.
+fn placeholders(s: &str) -> impl Iterator<Item = &str> {
+    s.split('{').skip(1).filter_map(|part| {
+        let (name, _) = part.split_once('}')?;
+        let is_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        is_name.then_some(name)
+    })
+}
+
+fn collect_strings<'a>(value: &'a toml::Value, strings: &mut Vec<&'a str>) {
+    match value {
+        toml::Value::String(s) => strings.push(s),
+        toml::Value::Array(array) => array
+            .iter()
+            .for_each(|value| collect_strings(value, strings)),
+        toml::Value::Table(table) => table
+            .iter()
+            .for_each(|(_, value)| collect_strings(value, strings)),
+        _ => {}
+    }
+}
+
+fn substitute_value(value: &mut toml::Value, variables: &[(&str, &str)]) {
+    match value {
+        toml::Value::String(s) => *s = substitute(s, variables),
+        toml::Value::Array(array) => array
+            .iter_mut()
+            .for_each(|value| substitute_value(value, variables)),
+        toml::Value::Table(table) => table
+            .iter_mut()
+            .for_each(|(_, value)| substitute_value(value, variables)),
+        _ => {}
+    }
+}
+
+/// Comparison settings that can be set for a metric, a group of metrics or as a default.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct Settings {
     significance_threshold: Option<f32>,
+    direction: Option<Direction>,
+}
+
+impl Settings {
+    const KEYS: &'static [&'static str] = &["significance_threshold", "direction"];
+
+    fn or(self, other: &Self) -> Self {
+        Self {
+            significance_threshold: self.significance_threshold.or(other.significance_threshold),
+            direction: self.direction.or(other.direction),
+        }
+    }
+}
+
+/// The preferred direction of change for a metric.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    #[default]
+    LowerIsBetter,
+    HigherIsBetter,
 }
 
 impl Metric {
-    fn generate(&self, root: &Path) -> Option<usize> {
-        self.def.generate(root)
+    fn generate(&self, id: &str, root: &Path) -> Result<Vec<(String, usize)>> {
+        self.def.generate(id, root)
+    }
+
+    /// Returns the IDs of all values produced by this metric that are present in the baseline or
+    /// in the test values.  Values in the baseline that use a previous ID of this metric are
+    /// mapped to the current ID.
+    fn value_ids(&self, id: &str, baseline: &Values, test: &Values) -> Vec<String> {
+        if self.def.has_sub_metrics() {
+            let mut ids = BTreeSet::new();
+            ids.extend(sub_ids(test.ids(), id).map(|suffix| format!("{id}.{suffix}")));
+            for old_id in iter::once(id).chain(self.renamed_from.iter().map(String::as_str)) {
+                ids.extend(sub_ids(baseline.ids(), old_id).map(|suffix| format!("{id}.{suffix}")));
+            }
+            iter::once(id.to_owned()).chain(ids).collect()
+        } else {
+            vec![id.to_owned()]
+        }
+    }
+
+    /// Returns the IDs that the value `value_id` of this metric could have in the baseline,
+    /// starting with the current ID.
+    fn old_ids(&self, id: &str, value_id: &str) -> Vec<String> {
+        let suffix = value_id.strip_prefix(id).unwrap_or_default();
+        iter::once(value_id.to_owned())
+            .chain(
+                self.renamed_from
+                    .iter()
+                    .map(|old_id| format!("{old_id}{suffix}")),
+            )
+            .collect()
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Returns true if `value_id` is the ID of the metric `id` or of one of its sub-metrics.
+fn is_value_of(value_id: &str, id: &str) -> bool {
+    value_id
+        .strip_prefix(id)
+        .is_some_and(|suffix| suffix.is_empty() || suffix.starts_with('.'))
+}
+
+/// Returns the suffixes of all sub-metric IDs of the metric `id`.
+fn sub_ids<'a>(ids: impl Iterator<Item = &'a str>, id: &str) -> impl Iterator<Item = &'a str> {
+    let prefix = format!("{id}.");
+    ids.filter_map(move |value_id| value_id.strip_prefix(&prefix))
+}
+
+#[derive(Clone, Debug, Deserialize)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 enum MetricDef {
+    Clippy(Clippy),
     FileSize(FileSize),
 }
 
 impl MetricDef {
-    fn generate(&self, root: &Path) -> Option<usize> {
+    fn generate(&self, id: &str, root: &Path) -> Result<Vec<(String, usize)>> {
         match self {
-            Self::FileSize(metric) => metric.generate(root),
+            Self::Clippy(metric) => metric
+                .generate(root)
+                .map(|diagnostics| diagnostics.values(id)),
+            Self::FileSize(metric) => metric
+                .generate(root)
+                .map(|value| vec![(id.to_owned(), value)]),
+        }
+    }
+
+    fn has_sub_metrics(&self) -> bool {
+        matches!(self, Self::Clippy(_))
+    }
+}
+
+/// Counts the diagnostics emitted by `cargo clippy`.
+///
+/// If `input` is set, the JSON messages are read from that file instead of running clippy.
+#[derive(Clone, Debug, Deserialize)]
+struct Clippy {
+    input: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    by_lint: bool,
+}
+
+impl Clippy {
+    fn generate(&self, root: &Path) -> Result<Diagnostics> {
+        let messages = if let Some(input) = &self.input {
+            let path = root.join(input);
+            fs::read_to_string(&path)
+                .with_context(|| format!("failed to read clippy messages '{}'", path.display()))?
+        } else {
+            self.run(root)?
+        };
+        Ok(Diagnostics::parse(&messages, self.by_lint))
+    }
+
+    fn run(&self, root: &Path) -> Result<String> {
+        debug!("Running cargo clippy in '{}'", root.display());
+        let output = Command::new("cargo")
+            .current_dir(root)
+            .arg("clippy")
+            .arg("--message-format=json")
+            .args(&self.args)
+            .output()
+            .context("failed to run cargo clippy")?;
+        // cargo clippy also fails if the code does not compile, but the errors are still reported
+        // in the output, so we only check that we actually received some messages.
+        let messages =
+            String::from_utf8(output.stdout).context("failed to decode clippy output as UTF-8")?;
+        anyhow::ensure!(
+            !messages.is_empty(),
+            "cargo clippy failed with status code {} and produced no messages",
+            output.status
+        );
+        Ok(messages)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Diagnostics {
+    levels: BTreeMap<String, usize>,
+    lints: BTreeMap<String, usize>,
+}
+
+impl Diagnostics {
+    fn parse(messages: &str, by_lint: bool) -> Self {
+        let mut diagnostics = Self::default();
+        for level in ["error", "warning"] {
+            diagnostics.levels.insert(level.to_owned(), 0);
         }
+        for line in messages.lines() {
+            let Ok(message) = serde_json::from_str::<CargoMessage>(line) else {
+                continue;
+            };
+            let Some(diagnostic) = message.message else {
+                continue;
+            };
+            // summaries like "2 warnings emitted" are also reported as diagnostics without spans
+            if message.reason != "compiler-message" || diagnostic.spans.is_empty() {
+                continue;
+            }
+            *diagnostics.levels.entry(diagnostic.level).or_default() += 1;
+            if by_lint {
+                if let Some(code) = diagnostic.code {
+                    *diagnostics.lints.entry(code.code).or_default() += 1;
+                }
+            }
+        }
+        diagnostics
+    }
+
+    fn values(&self, id: &str) -> Vec<(String, usize)> {
+        let total = self.levels.values().sum();
+        let levels = self.levels.iter().chain(&self.lints);
+        [(id.to_owned(), total)]
+            .into_iter()
+            .chain(levels.map(|(name, count)| (format!("{id}.{name}"), *count)))
+            .collect()
     }
 }
 
 #[derive(Debug, Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<CargoDiagnostic>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CargoDiagnostic {
+    level: String,
+    code: Option<CargoDiagnosticCode>,
+    spans: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CargoDiagnosticCode {
+    code: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
 struct FileSize {
     input: String,
 }
 
 impl FileSize {
-    fn generate(&self, root: &Path) -> Option<usize> {
+    fn generate(&self, root: &Path) -> Result<usize> {
         let path = root.join(&self.input);
-        let metadata = fs::metadata(path).ok()?;
-        metadata.len().try_into().ok()
+        let metadata = fs::metadata(&path)
+            .with_context(|| format!("failed to read metadata of '{}'", path.display()))?;
+        metadata
+            .len()
+            .try_into()
+            .with_context(|| format!("size of '{}' is out of range", path.display()))
     }
 }
 
@@ -96,6 +610,14 @@ pub enum Values {
 }
 
 impl Values {
+    #[cfg(feature = "sqlite")]
+    pub fn new(values: BTreeMap<String, usize>) -> Self {
+        Self::V1(ValuesV1 {
+            values,
+            ..Default::default()
+        })
+    }
+
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
         let path = path.as_ref();
         let s = fs::read_to_string(path)
@@ -104,12 +626,139 @@ impl Values {
             .with_context(|| format!("failed to parse values file '{}'", path.display()))
     }
 
-    pub fn get(&self, metric: &String) -> Option<usize> {
+    pub fn get(&self, metric: &str) -> Option<usize> {
         match self {
             Self::V1(values) => values.values.get(metric).copied(),
         }
     }
 
+    /// Returns the first value that is present for one of the given IDs.
+    pub fn get_any(&self, metrics: &[String]) -> Option<usize> {
+        metrics.iter().find_map(|metric| self.get(metric))
+    }
+
+    /// Returns the fingerprint of the first of the given metric IDs that has one.
+    fn fingerprint(&self, metrics: &[String]) -> Option<&str> {
+        match self {
+            Self::V1(values) => metrics
+                .iter()
+                .find_map(|metric| values.fingerprints.get(metric))
+                .map(String::as_str),
+        }
+    }
+
+    /// Returns the error for the first of the given metric IDs that could not be generated.
+    fn error(&self, metrics: &[String]) -> Option<&str> {
+        match self {
+            Self::V1(values) => metrics
+                .iter()
+                .find_map(|metric| values.errors.get(metric))
+                .map(String::as_str),
+        }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = (&str, &str)> {
+        match self {
+            Self::V1(values) => values
+                .errors
+                .iter()
+                .map(|(metric, error)| (metric.as_str(), error.as_str())),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::V1(values) => values.values.len(),
+        }
+    }
+
+    fn ids(&self) -> impl Iterator<Item = &str> {
+        match self {
+            Self::V1(values) => values.values.keys().map(String::as_str),
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        match self {
+            Self::V1(values) => values
+                .values
+                .iter()
+                .map(|(metric, value)| (metric.as_str(), *value)),
+        }
+    }
+
+    /// Merges newer values for the same commit into these values.
+    ///
+    /// All values of the metrics that were generated again, as indicated by their fingerprints,
+    /// are replaced, including sub-metrics and errors.  Values of other metrics are kept, so
+    /// merging the values of two runs that generated disjoint sets of metrics combines them.
+    pub fn update(&mut self, other: Values) {
+        let Self::V1(values) = self;
+        let Self::V1(other) = other;
+        for id in other.fingerprints.keys() {
+            values
+                .values
+                .retain(|value_id, _| !is_value_of(value_id, id));
+            values.errors.remove(id);
+        }
+        values.values.extend(other.values);
+        values.fingerprints.extend(other.fingerprints);
+        values.errors.extend(other.errors);
+    }
+
+    /// Combines several partial values files, for example from parallel CI jobs, into one.
+    ///
+    /// Fails if a value or the fingerprint of a metric differs between the files.  Errors are only
+    /// kept for metrics that have no values in any of the files.
+    pub fn merge(values: impl IntoIterator<Item = Values>) -> Result<Values> {
+        let mut merged = ValuesV1::default();
+        let mut errors = BTreeMap::new();
+        let mut conflicts = Vec::new();
+        for Self::V1(values) in values {
+            for (id, value) in values.values {
+                match merged.values.entry(id) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(value);
+                    }
+                    Entry::Occupied(entry) if *entry.get() != value => {
+                        conflicts.push(format!("{} ({} != {})", entry.key(), entry.get(), value));
+                    }
+                    Entry::Occupied(_) => {}
+                }
+            }
+            for (id, fingerprint) in values.fingerprints {
+                match merged.fingerprints.entry(id) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(fingerprint);
+                    }
+                    Entry::Occupied(entry) if *entry.get() != fingerprint => {
+                        conflicts.push(format!("{} (different definitions)", entry.key()));
+                    }
+                    Entry::Occupied(_) => {}
+                }
+            }
+            for (id, error) in values.errors {
+                errors.entry(id).or_insert(error);
+            }
+        }
+        anyhow::ensure!(
+            conflicts.is_empty(),
+            "conflicting values for metrics: {}",
+            conflicts.join(", ")
+        );
+        for (id, error) in errors {
+            if !merged
+                .values
+                .keys()
+                .any(|value_id| is_value_of(value_id, &id))
+            {
+                merged.errors.insert(id, error);
+            }
+        }
+        Ok(Self::V1(merged))
+    }
+
     pub fn format(&self) -> Result<String> {
         toml::to_string_pretty(self).context("failed to format metric values")
     }
@@ -118,17 +767,95 @@ impl Values {
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct ValuesV1 {
     values: BTreeMap<String, usize>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    fingerprints: BTreeMap<String, String>,
+    /// Errors for the metrics that could not be generated.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    errors: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Default)]
 pub struct Comparisons {
     pub significant: Vec<Comparison>,
     pub insignificant: Vec<Comparison>,
+    pub unchanged: Vec<Comparison>,
+    pub added: Vec<Comparison>,
+    pub removed: Vec<Comparison>,
+    /// Comparisons for metrics whose definition changed since the values were generated.
+    pub stale: Vec<Comparison>,
+    pub failed: Vec<Failure>,
+}
+
+impl Comparisons {
+    fn push(&mut self, comparison: Comparison, significance_threshold: Option<f32>) {
+        match (comparison.old_value, comparison.new_value) {
+            (None, None) => debug!("No values for metric {}", comparison.metric),
+            (None, Some(_)) => self.added.push(comparison),
+            (Some(_), None) => self.removed.push(comparison),
+            (Some(_), Some(_)) => {
+                let is_unchanged = comparison
+                    .absolute_change
+                    .map(|change| *change == 0)
+                    .unwrap_or_default();
+                let is_significant = significance_threshold
+                    .zip(comparison.relative_change)
+                    .map(|(threshold, change)| change.abs() >= threshold)
+                    .unwrap_or(true);
+                if is_unchanged {
+                    self.unchanged.push(comparison);
+                } else if is_significant {
+                    self.significant.push(comparison);
+                } else {
+                    self.insignificant.push(comparison);
+                }
+            }
+        }
+    }
+
+    /// Returns true if there are no significant changes, failures, added, removed or stale
+    /// metrics.
+    pub fn is_unchanged(&self) -> bool {
+        self.significant.is_empty()
+            && self.failed.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.stale.is_empty()
+    }
+
+    pub fn check(&self, defaults: &Defaults, strict: bool) -> Result<()> {
+        if strict && !self.failed.is_empty() {
+            let metrics: Vec<_> = self
+                .failed
+                .iter()
+                .map(|failure| failure.metric.as_str())
+                .collect();
+            anyhow::bail!("metrics could not be generated: {}", metrics.join(", "));
+        }
+        if defaults.fail_on_removed && !self.removed.is_empty() {
+            let metrics: Vec<_> = self
+                .removed
+                .iter()
+                .map(|comparison| comparison.metric.as_str())
+                .collect();
+            anyhow::bail!("metrics were removed: {}", metrics.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// A metric that could not be generated for the baseline or test values.
+#[derive(Debug)]
+pub struct Failure {
+    pub metric: String,
+    pub baseline: Option<String>,
+    pub test: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Comparison {
     pub metric: String,
+    pub group: Option<String>,
+    pub direction: Direction,
     pub old_value: Option<AbsoluteValue>,
     pub new_value: Option<AbsoluteValue>,
     pub absolute_change: Option<AbsoluteChange>,
@@ -144,19 +871,58 @@ impl Comparison {
                 if let Ok(new_value) = isize::try_from(new_value) {
                     if let Some(delta) = new_value.checked_sub(old_value) {
                         absolute_change = Some(delta);
-                        relative_change = Some((delta as f32) / (old_value as f32));
+                        // the relative change from zero is undefined
+                        if old_value != 0 {
+                            relative_change = Some((delta as f32) / (old_value as f32));
+                        }
                     }
                 }
             }
         }
         Self {
             metric,
+            group: None,
+            direction: Direction::default(),
             old_value: old_value.map(AbsoluteValue),
             new_value: new_value.map(AbsoluteValue),
             absolute_change: absolute_change.map(AbsoluteChange),
             relative_change: relative_change.map(RelativeChange),
         }
     }
+
+    /// Returns the metric ID relative to its group.
+    pub fn name(&self) -> &str {
+        self.group
+            .as_deref()
+            .and_then(|group| self.metric.strip_prefix(group))
+            .and_then(|name| name.strip_prefix('.'))
+            .unwrap_or(&self.metric)
+    }
+
+    pub fn trend(&self) -> Option<Trend> {
+        let absolute_change = self.absolute_change?;
+        let trend = if absolute_change.is_positive() {
+            match self.direction {
+                Direction::LowerIsBetter => Trend::Worse,
+                Direction::HigherIsBetter => Trend::Better,
+            }
+        } else if absolute_change.is_negative() {
+            match self.direction {
+                Direction::LowerIsBetter => Trend::Better,
+                Direction::HigherIsBetter => Trend::Worse,
+            }
+        } else {
+            Trend::Unchanged
+        };
+        Some(trend)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trend {
+    Better,
+    Worse,
+    Unchanged,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -230,6 +996,470 @@ fn display_int(f: &mut Formatter<'_>, s: &str) -> fmt::Result {
 mod tests {
     use super::*;
 
+    #[test]
+    fn deserialize_metric_groups() {
+        let metrics: Metrics = toml::from_str(
+            r#"
+            [top]
+            type = "file-size"
+            input = "top"
+
+            [firmware]
+            significance_threshold = 0.05
+            direction = "higher-is-better"
+
+            [firmware.nk3xn]
+            type = "file-size"
+            input = "nk3xn"
+
+            [firmware.nk3am]
+            significance_threshold = 0.2
+
+            [firmware.nk3am.test]
+            type = "file-size"
+            input = "nk3am-test"
+            direction = "lower-is-better"
+            "#,
+        )
+        .unwrap();
+        let ids: Vec<_> = metrics.0.keys().map(String::as_str).collect();
+        assert_eq!(ids, ["firmware.nk3am.test", "firmware.nk3xn", "top"]);
+
+        let metric = &metrics.0["top"];
+        assert_eq!(metric.group, None);
+        assert_eq!(metric.settings.significance_threshold, None);
+        assert_eq!(metric.settings.direction, None);
+
+        let metric = &metrics.0["firmware.nk3xn"];
+        assert_eq!(metric.group.as_deref(), Some("firmware"));
+        assert_eq!(metric.settings.significance_threshold, Some(0.05));
+        assert_eq!(metric.settings.direction, Some(Direction::HigherIsBetter));
+
+        let metric = &metrics.0["firmware.nk3am.test"];
+        assert_eq!(metric.group.as_deref(), Some("firmware.nk3am"));
+        assert_eq!(metric.settings.significance_threshold, Some(0.2));
+        assert_eq!(metric.settings.direction, Some(Direction::LowerIsBetter));
+
+        let result: Result<Metrics, _> = toml::from_str(
+            r#"
+            [firmware]
+            significance_treshold = 0.05
+
+            [firmware.nk3xn]
+            type = "file-size"
+            input = "nk3xn"
+            "#,
+        );
+        assert_eq!(
+            result.unwrap_err().message().trim_end(),
+            "unknown setting 'significance_treshold' of group 'firmware', expected one of: \
+             significance_threshold, direction"
+        );
+    }
+
+    #[test]
+    fn deserialize_metric_matrix() {
+        let metrics: Metrics = toml::from_str(
+            r#"
+            [firmware."{board}-{variant}"]
+            type = "file-size"
+            input = "target/{board}/{variant}/firmware.bin"
+            matrix = { board = ["nk3xn", "nk3am"], variant = ["test", "release"] }
+            "#,
+        )
+        .unwrap();
+        let ids: Vec<_> = metrics.0.keys().map(String::as_str).collect();
+        assert_eq!(
+            ids,
+            [
+                "firmware.nk3am-release",
+                "firmware.nk3am-test",
+                "firmware.nk3xn-release",
+                "firmware.nk3xn-test",
+            ]
+        );
+        let MetricDef::FileSize(metric) = &metrics.0["firmware.nk3am-test"].def else {
+            panic!("unexpected metric type");
+        };
+        assert_eq!(metric.input, "target/nk3am/test/firmware.bin");
+
+        let result: Result<Metrics, _> = toml::from_str(
+            r#"
+            [firmware]
+            type = "file-size"
+            input = "target/{board}/firmware.bin"
+            matrix = { board = ["nk3xn", "nk3am"] }
+            "#,
+        );
+        assert!(result.is_err());
+
+        let error = |matrix: &str| {
+            let config = format!(
+                r#"
+                [firmware."{{board}}"]
+                type = "file-size"
+                input = "target/{{board}}/{{variant}}/firmware.bin"
+                matrix = {matrix}
+                "#
+            );
+            toml::from_str::<Metrics>(&config)
+                .unwrap_err()
+                .message()
+                .trim_end()
+                .to_owned()
+        };
+        assert_eq!(
+            error(r#"{ board = ["nk3xn"] }"#),
+            "invalid metric template 'firmware.{board}': unknown placeholder '{variant}'"
+        );
+        assert_eq!(
+            error(r#"{ board = ["nk3xn"], variant = [] }"#),
+            "invalid metric template 'firmware.{board}': matrix variable 'variant' has no values"
+        );
+        assert_eq!(
+            error("{}"),
+            "invalid metric template 'firmware.{board}': matrix has no variables"
+        );
+    }
+
+    #[test]
+    fn compare_categories() {
+        let metrics: Metrics = toml::from_str(
+            r#"
+            [a]
+            type = "file-size"
+            input = "a"
+            [b]
+            type = "file-size"
+            input = "b"
+            significance_threshold = 0.5
+            [c]
+            type = "file-size"
+            input = "c"
+            [added]
+            type = "file-size"
+            input = "added"
+            [removed]
+            type = "file-size"
+            input = "removed"
+            [missing]
+            type = "file-size"
+            input = "missing"
+            "#,
+        )
+        .unwrap();
+        let baseline: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { a = 100, b = 100, c = 100, removed = 100 }
+            "#,
+        )
+        .unwrap();
+        let test: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { a = 200, b = 110, c = 100, added = 100 }
+            "#,
+        )
+        .unwrap();
+        let mut defaults = Defaults::default();
+        let comparisons = metrics.compare(&defaults, &baseline, &test, false);
+        let ids = |comparisons: &[Comparison]| -> Vec<String> {
+            comparisons.iter().map(|c| c.metric.clone()).collect()
+        };
+        assert_eq!(ids(&comparisons.significant), ["a"]);
+        assert_eq!(ids(&comparisons.insignificant), ["b"]);
+        assert_eq!(ids(&comparisons.unchanged), ["c"]);
+        assert_eq!(ids(&comparisons.added), ["added"]);
+        assert_eq!(ids(&comparisons.removed), ["removed"]);
+        assert!(comparisons.check(&defaults, false).is_ok());
+
+        defaults.fail_on_removed = true;
+        assert!(comparisons.check(&defaults, false).is_err());
+    }
+
+    #[test]
+    fn compare_unconfigured() {
+        let metrics: Metrics = toml::from_str(
+            r#"
+            [a]
+            type = "file-size"
+            input = "a"
+            significance_threshold = 0.5
+            "#,
+        )
+        .unwrap();
+        let baseline: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { a = 100, "group.b" = 100, c = 100 }
+            "#,
+        )
+        .unwrap();
+        let test: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { a = 110, "group.b" = 110, d = 100 }
+            "#,
+        )
+        .unwrap();
+        let defaults = Defaults::default();
+
+        let comparisons = metrics.compare(&defaults, &baseline, &test, false);
+        assert_eq!(comparisons.insignificant.len(), 1);
+        assert!(comparisons.significant.is_empty());
+        assert!(comparisons.added.is_empty());
+        assert!(comparisons.removed.is_empty());
+        assert!(comparisons.is_unchanged());
+
+        let comparisons = metrics.compare(&defaults, &baseline, &test, true);
+        assert_eq!(comparisons.insignificant[0].metric, "a");
+        assert_eq!(comparisons.significant[0].metric, "group.b");
+        assert_eq!(comparisons.significant[0].group.as_deref(), Some("group"));
+        assert_eq!(comparisons.significant[0].name(), "b");
+        assert_eq!(comparisons.added[0].metric, "d");
+        assert_eq!(comparisons.removed[0].metric, "c");
+
+        let comparisons = Metrics::default().compare(&defaults, &baseline, &test, true);
+        assert_eq!(comparisons.significant.len(), 2);
+    }
+
+    #[test]
+    fn compare_renamed() {
+        let metrics: Metrics = toml::from_str(
+            r#"
+            [size]
+            type = "file-size"
+            input = "a"
+            renamed_from = ["old-size", "older-size"]
+            [warnings]
+            type = "clippy"
+            renamed_from = ["clippy"]
+            "#,
+        )
+        .unwrap();
+        let baseline: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { older-size = 100, clippy = 1, "clippy.warning" = 1 }
+            "#,
+        )
+        .unwrap();
+        let test: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { size = 200, warnings = 2, "warnings.warning" = 1, "warnings.error" = 1 }
+            "#,
+        )
+        .unwrap();
+        let comparisons = metrics.compare(&Defaults::default(), &baseline, &test, true);
+        let ids: Vec<_> = comparisons
+            .significant
+            .iter()
+            .map(|c| (c.metric.as_str(), c.old_value.map(|value| *value)))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("size", Some(100)),
+                ("warnings", Some(1)),
+                ("warnings.error", Some(0)),
+            ]
+        );
+        assert_eq!(comparisons.unchanged[0].metric, "warnings.warning");
+        assert!(comparisons.added.is_empty());
+        assert!(comparisons.removed.is_empty());
+    }
+
+    #[test]
+    fn compare_failed() {
+        let metrics: Metrics = toml::from_str(
+            r#"
+            [size]
+            type = "file-size"
+            input = "a"
+            [warnings]
+            type = "clippy"
+            "#,
+        )
+        .unwrap();
+        let baseline: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { size = 100, warnings = 1, "warnings.warning" = 1 }
+            "#,
+        )
+        .unwrap();
+        let test: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { size = 100 }
+            errors = { warnings = "failed to run cargo clippy" }
+            "#,
+        )
+        .unwrap();
+        let comparisons = metrics.compare(&Defaults::default(), &baseline, &test, true);
+        assert!(!comparisons.is_unchanged());
+        assert_eq!(comparisons.failed.len(), 1);
+        assert_eq!(comparisons.failed[0].metric, "warnings");
+        assert_eq!(comparisons.failed[0].baseline, None);
+        assert_eq!(
+            comparisons.failed[0].test.as_deref(),
+            Some("failed to run cargo clippy")
+        );
+        assert_eq!(comparisons.unchanged[0].metric, "size");
+        assert!(comparisons.removed.is_empty());
+        assert!(comparisons.check(&Defaults::default(), false).is_ok());
+        assert!(comparisons.check(&Defaults::default(), true).is_err());
+
+        // without configuration, errors are reported for all values
+        let comparisons = Metrics::default().compare(&Defaults::default(), &baseline, &test, true);
+        assert_eq!(comparisons.failed.len(), 1);
+        assert_eq!(comparisons.failed[0].metric, "warnings");
+        assert_eq!(
+            comparisons.failed[0].test.as_deref(),
+            Some("failed to run cargo clippy")
+        );
+        assert_eq!(comparisons.unchanged.len(), 1);
+        assert_eq!(comparisons.unchanged[0].metric, "size");
+        assert!(comparisons.removed.is_empty());
+        assert!(comparisons.check(&Defaults::default(), true).is_err());
+    }
+
+    #[test]
+    fn update_values() {
+        let mut values: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { size = 100, warnings = 2, "warnings.warning" = 1, "warnings.error" = 1 }
+            fingerprints = { size = "a", warnings = "b" }
+            "#,
+        )
+        .unwrap();
+        let other: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { other = 5, warnings = 1, "warnings.warning" = 1 }
+            fingerprints = { other = "c", warnings = "d" }
+            "#,
+        )
+        .unwrap();
+        values.update(other);
+        let Values::V1(values) = values;
+        assert_eq!(
+            values.values,
+            BTreeMap::from([
+                ("other".to_owned(), 5),
+                ("size".to_owned(), 100),
+                ("warnings".to_owned(), 1),
+                ("warnings.warning".to_owned(), 1),
+            ])
+        );
+        assert_eq!(values.fingerprints["warnings"], "d");
+    }
+
+    #[test]
+    fn merge_values() {
+        let a: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { shared = 1, a = 2 }
+            fingerprints = { shared = "x", a = "y", b = "z" }
+            errors = { b = "missing build output" }
+            "#,
+        )
+        .unwrap();
+        let b: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { shared = 1, b = 3, "b.sub" = 4 }
+            fingerprints = { shared = "x", a = "y", b = "z", c = "w" }
+            errors = { a = "missing build output", c = "missing build output" }
+            "#,
+        )
+        .unwrap();
+        let Values::V1(merged) = Values::merge([a, b]).unwrap();
+        assert_eq!(
+            merged.values,
+            BTreeMap::from([
+                ("a".to_owned(), 2),
+                ("b".to_owned(), 3),
+                ("b.sub".to_owned(), 4),
+                ("shared".to_owned(), 1),
+            ])
+        );
+        assert_eq!(merged.fingerprints.len(), 4);
+        assert_eq!(
+            merged.errors,
+            BTreeMap::from([("c".to_owned(), "missing build output".to_owned())])
+        );
+
+        let a: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { shared = 1 }
+            "#,
+        )
+        .unwrap();
+        let b: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { shared = 2 }
+            "#,
+        )
+        .unwrap();
+        let err = Values::merge([a, b]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "conflicting values for metrics: shared (1 != 2)"
+        );
+    }
+
+    #[test]
+    fn compare_stale() {
+        let old: Metrics = toml::from_str(
+            r#"
+            [a]
+            type = "file-size"
+            input = "a"
+            [b]
+            type = "file-size"
+            input = "b"
+            "#,
+        )
+        .unwrap();
+        let new: Metrics = toml::from_str(
+            r#"
+            [a]
+            type = "file-size"
+            input = "a"
+            significance_threshold = 0.5
+            [b]
+            type = "file-size"
+            input = "c"
+            "#,
+        )
+        .unwrap();
+        let Values::V1(mut values) = old.generate(Path::new("/nonexistent"));
+        values.errors.clear();
+        values.values.insert("a".to_owned(), 100);
+        values.values.insert("b".to_owned(), 100);
+        let baseline = Values::V1(values);
+        let test: Values = toml::from_str(
+            r#"
+            version = "1"
+            values = { a = 200, b = 200 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(new.stale(&baseline), ["b"]);
+        assert!(new.stale(&test).is_empty());
+        let comparisons = new.compare(&Defaults::default(), &baseline, &test, false);
+        assert_eq!(comparisons.significant[0].metric, "a");
+        assert_eq!(comparisons.stale[0].metric, "b");
+        assert_eq!(comparisons.significant.len() + comparisons.stale.len(), 2);
+    }
+
     #[test]
     fn display_absolute_value() {
         assert_eq!(AbsoluteValue(0).to_string(), "0");
@@ -250,6 +1480,41 @@ mod tests {
         assert_eq!(AbsoluteChange(-1230000).to_string(), "-1,230,000");
     }
 
+    #[test]
+    fn parse_clippy_diagnostics() {
+        let messages = r#"{"reason":"compiler-artifact","package_id":"foo"}
+{"reason":"compiler-message","message":{"level":"warning","code":{"code":"unused_variables"},"spans":[{}]}}
+{"reason":"compiler-message","message":{"level":"warning","code":{"code":"clippy::needless_return"},"spans":[{}]}}
+{"reason":"compiler-message","message":{"level":"warning","code":{"code":"unused_variables"},"spans":[{}]}}
+{"reason":"compiler-message","message":{"level":"error","code":null,"spans":[{}]}}
+{"reason":"compiler-message","message":{"level":"warning","code":null,"spans":[]}}
+{"reason":"build-finished","success":false}"#;
+        let diagnostics = Diagnostics::parse(messages, true);
+        assert_eq!(
+            diagnostics.values("clippy"),
+            [
+                ("clippy".to_owned(), 4),
+                ("clippy.error".to_owned(), 1),
+                ("clippy.warning".to_owned(), 3),
+                ("clippy.clippy::needless_return".to_owned(), 1),
+                ("clippy.unused_variables".to_owned(), 2),
+            ]
+        );
+        let diagnostics = Diagnostics::parse(messages, false);
+        assert_eq!(diagnostics.values("clippy").len(), 3);
+    }
+
+    #[test]
+    fn compare_from_zero() {
+        let comparison = Comparison::new("warnings".to_owned(), Some(0), Some(2));
+        assert_eq!(comparison.absolute_change.map(|change| *change), Some(2));
+        assert!(comparison.relative_change.is_none());
+
+        let mut comparisons = Comparisons::default();
+        comparisons.push(comparison, Some(0.1));
+        assert_eq!(comparisons.significant.len(), 1);
+    }
+
     #[test]
     fn display_relative_change() {
         assert_eq!(RelativeChange(0.0).to_string(), "+0.00%");
@@ -0,0 +1,166 @@
+use std::{
+    fs::{self, File},
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use log::info;
+
+use crate::{
+    config::DirectoryConfig,
+    storage::{Backend, Loaded},
+};
+
+/// A directory outside of the repository that stores the values files in a subdirectory per
+/// project, for example on a network share or in a CI runner cache.
+pub struct Directory {
+    path: PathBuf,
+}
+
+impl Directory {
+    pub fn new(config: &DirectoryConfig) -> Self {
+        Self {
+            path: config.path.join(&config.project),
+        }
+    }
+
+    fn file(&self, commit: &str) -> PathBuf {
+        self.path.join(format!("{commit}.toml"))
+    }
+}
+
+impl Backend for Directory {
+    fn name(&self) -> String {
+        format!("directory '{}'", self.path.display())
+    }
+
+    fn load(&self, commit: &str) -> Result<Option<Loaded>> {
+        let file = self.file(commit);
+        if file.exists() {
+            info!("Reading values file '{}'", file.display());
+            fs::read_to_string(&file)
+                .map(|data| Some(data.into()))
+                .with_context(|| format!("failed to read values file '{}'", file.display()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn store(&self, commit: &str, data: &str) -> Result<()> {
+        fs::create_dir_all(&self.path)
+            .with_context(|| format!("failed to create directory '{}'", self.path.display()))?;
+        let _lock = lock(&self.path)?;
+        let file = self.file(commit);
+        info!("Writing data to values file '{}'", file.display());
+        write_atomic(&file, data)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        list_values_files(&self.path)
+    }
+}
+
+/// Locks the directory until the returned file is dropped.
+///
+/// The lock is advisory, so it only protects against other processes that also use this function.
+pub fn lock(path: &Path) -> Result<File> {
+    let lock_file = path.join(".lock");
+    let file = File::create(&lock_file)
+        .with_context(|| format!("failed to create lock file '{}'", lock_file.display()))?;
+    file.lock()
+        .with_context(|| format!("failed to lock '{}'", lock_file.display()))?;
+    Ok(file)
+}
+
+/// Writes `data` to a temporary file next to `path` and then renames it to `path` so that
+/// readers never see a partially written file.
+///
+/// The temporary file has a random name, so writers on different hosts sharing the directory
+/// don't interfere with each other.
+pub fn write_atomic(path: &Path, data: &str) -> Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut builder = tempfile::Builder::new();
+    // like fs::write, create the file with the default permissions instead of 0600
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        builder.permissions(fs::Permissions::from_mode(0o666));
+    }
+    let mut tmp = builder
+        .tempfile_in(dir)
+        .with_context(|| format!("failed to create temporary file in '{}'", dir.display()))?;
+    tmp.write_all(data.as_bytes())
+        .with_context(|| format!("failed to write temporary file '{}'", tmp.path().display()))?;
+    tmp.persist(path)
+        .map_err(|err| err.error)
+        .with_context(|| format!("failed to rename temporary file to '{}'", path.display()))?;
+    Ok(())
+}
+
+/// Returns the commits of all `<commit>.toml` files in a directory.
+pub fn list_values_files(path: &Path) -> Result<Vec<String>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(path)
+        .with_context(|| format!("failed to read directory '{}'", path.display()))?;
+    let mut commits = Vec::new();
+    for entry in entries {
+        let entry =
+            entry.with_context(|| format!("failed to read directory '{}'", path.display()))?;
+        let path = entry.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            if let Some(commit) = path.file_stem().and_then(|stem| stem.to_str()) {
+                commits.push(commit.to_owned());
+            }
+        }
+    }
+    commits.sort();
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn store_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = Directory::new(&DirectoryConfig {
+            path: dir.path().to_owned(),
+            project: "firmware".to_owned(),
+        });
+        assert!(directory.list().unwrap().is_empty());
+        assert!(directory.load("c1").unwrap().is_none());
+
+        directory.store("c1", "a").unwrap();
+        directory.store("c2", "b").unwrap();
+        directory.store("c1", "c").unwrap();
+        assert_eq!(directory.load("c1").unwrap().unwrap().data, "c");
+        assert_eq!(directory.load("c2").unwrap().unwrap().data, "b");
+        let mut commits = directory.list().unwrap();
+        commits.sort();
+        assert_eq!(commits, ["c1", "c2"]);
+    }
+
+    #[test]
+    fn write_file_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("values.toml");
+        write_atomic(&path, "a").unwrap();
+        write_atomic(&path, "b").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b");
+        // no temporary files are left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let missing = dir.path().join("missing").join("values.toml");
+        assert!(write_atomic(&missing, "a").is_err());
+    }
+}
@@ -0,0 +1,382 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+};
+
+use chrono::{DateTime, Utc};
+use git2::{
+    DiffFormat, DiffOptions, ErrorCode, Oid, Signature, Sort, StatusOptions, WorktreeAddOptions,
+    WorktreePruneOptions,
+};
+use log::{debug, warn};
+use sha2::{Digest as _, Sha256};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("'{0}' is not inside a Git repository")]
+    NotFound(PathBuf),
+    #[error("Git repository '{0}' has no working directory")]
+    Bare(PathBuf),
+    #[error("failed to resolve Git revision '{0}'")]
+    Revision(String, #[source] git2::Error),
+    #[error("failed to find the merge base of '{0}' and HEAD")]
+    MergeBase(String, #[source] git2::Error),
+    #[error("Git note for commit {0} is not valid UTF-8")]
+    NoteEncoding(Oid),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// A temporary worktree, see [`Repository::add_worktree`].
+pub struct Worktree {
+    worktree: git2::Worktree,
+    path: PathBuf,
+}
+
+impl Worktree {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        debug!("Removing worktree '{}'", self.path.display());
+        let result = self.worktree.prune(Some(
+            WorktreePruneOptions::new().valid(true).working_tree(true),
+        ));
+        if let Err(err) = result {
+            warn!("Failed to remove worktree '{}': {err}", self.path.display());
+        }
+    }
+}
+
+/// A Git repository discovered from a path inside its working directory.
+pub struct Repository {
+    repo: git2::Repository,
+}
+
+impl Repository {
+    /// Discovers the repository containing the given path.
+    ///
+    /// This also supports worktrees and submodules where `.git` is a file.
+    pub fn discover(path: &Path) -> Result<Self> {
+        let repo = git2::Repository::discover(path).map_err(|err| {
+            if err.code() == ErrorCode::NotFound {
+                Error::NotFound(path.to_owned())
+            } else {
+                Error::Git(err)
+            }
+        })?;
+        if repo.is_bare() {
+            return Err(Error::Bare(repo.path().to_owned()));
+        }
+        Ok(Self { repo })
+    }
+
+    pub fn workdir(&self) -> &Path {
+        // discover rejects bare repositories
+        self.repo.workdir().unwrap_or_else(|| self.repo.path())
+    }
+
+    /// Returns true if there are no modified, staged or untracked files.
+    pub fn is_clean(&self) -> Result<bool> {
+        let mut options = StatusOptions::new();
+        options.include_untracked(true).include_ignored(false);
+        let statuses = self.repo.statuses(Some(&mut options))?;
+        Ok(statuses.is_empty())
+    }
+
+    /// Returns a hash of the uncommitted changes including untracked files, or `None` if the
+    /// working tree is clean.
+    pub fn diff_hash(&self) -> Result<Option<String>> {
+        if self.is_clean()? {
+            return Ok(None);
+        }
+        let head = self.repo.head()?.peel_to_tree()?;
+        let mut options = DiffOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .show_untracked_content(true);
+        let diff = self
+            .repo
+            .diff_tree_to_workdir_with_index(Some(&head), Some(&mut options))?;
+        let mut hasher = Sha256::new();
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            hasher.update([line.origin() as u8]);
+            hasher.update(line.content());
+            true
+        })?;
+        Ok(Some(hex::encode(&hasher.finalize()[..8])))
+    }
+
+    pub fn head_commit(&self) -> Result<String> {
+        self.resolve_commit("HEAD")
+    }
+
+    /// Returns the name of the checked out branch, or `None` for a detached HEAD.
+    #[cfg(feature = "sqlite")]
+    pub fn branch(&self) -> Option<String> {
+        let head = self.repo.head().ok()?;
+        if head.is_branch() {
+            head.shorthand().map(ToOwned::to_owned)
+        } else {
+            None
+        }
+    }
+
+    pub fn resolve_commit(&self, rev: &str) -> Result<String> {
+        self.find_commit(rev).map(|oid| oid.to_string())
+    }
+
+    pub fn merge_base(&self, base: &str) -> Result<String> {
+        let base_oid = self.find_commit(base)?;
+        let head_oid = self.find_commit("HEAD")?;
+        self.repo
+            .merge_base(base_oid, head_oid)
+            .map(|oid| oid.to_string())
+            .map_err(|err| Error::MergeBase(base.to_owned(), err))
+    }
+
+    /// Returns the commit and up to `n` of its first-parent ancestors, starting with the commit.
+    pub fn ancestors(&self, rev: &str, n: usize) -> Result<Vec<String>> {
+        let mut walk = self.repo.revwalk()?;
+        walk.set_sorting(Sort::TOPOLOGICAL)?;
+        walk.simplify_first_parent()?;
+        walk.push(self.find_commit(rev)?)?;
+        walk.take(n + 1)
+            .map(|oid| oid.map(|oid| oid.to_string()).map_err(From::from))
+            .collect()
+    }
+
+    /// Returns the commit time, or `None` if the commit does not exist in the repository.
+    pub fn commit_time(&self, rev: &str) -> Option<DateTime<Utc>> {
+        let oid = self.find_commit(rev).ok()?;
+        let commit = self.repo.find_commit(oid).ok()?;
+        DateTime::from_timestamp(commit.time().seconds(), 0)
+    }
+
+    /// Returns true if the commit is reachable from a local or remote branch.
+    pub fn is_reachable(&self, rev: &str) -> Result<bool> {
+        let Ok(oid) = self.find_commit(rev) else {
+            return Ok(false);
+        };
+        for branch in self.repo.branches(None)? {
+            let (branch, _) = branch?;
+            let Some(target) = branch.get().target() else {
+                continue;
+            };
+            if target == oid || self.repo.graph_descendant_of(target, oid)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Checks out the commit in a new temporary worktree that is removed when it is dropped.
+    pub fn add_worktree(&self, rev: &str) -> Result<Worktree> {
+        let oid = self.find_commit(rev)?;
+        let commit = self.repo.find_commit(oid)?;
+        let name = format!("repometrics-{}-{}", &oid.to_string()[..12], process::id());
+        let path = env::temp_dir().join(&name);
+        debug!("Creating worktree '{}' for commit {oid}", path.display());
+        // libgit2 can only create worktrees for branches, so we use a temporary branch and detach
+        // the worktree HEAD afterwards
+        let mut branch = self.repo.branch(&name, &commit, false)?;
+        let result = self
+            .repo
+            .worktree(
+                &name,
+                &path,
+                Some(WorktreeAddOptions::new().reference(Some(branch.get()))),
+            )
+            .and_then(|worktree| {
+                let repo = git2::Repository::open_from_worktree(&worktree)?;
+                repo.set_head_detached(oid)?;
+                Ok(worktree)
+            });
+        if let Err(err) = branch.delete() {
+            warn!("Failed to delete temporary branch {name}: {err}");
+        }
+        Ok(Worktree {
+            worktree: result?,
+            path,
+        })
+    }
+
+    pub fn read_note(&self, notes_ref: &str, rev: &str) -> Result<Option<String>> {
+        let oid = self.find_commit(rev)?;
+        let note = match self.repo.find_note(Some(notes_ref), oid) {
+            Ok(note) => note,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        note.message()
+            .map(|message| Some(message.to_owned()))
+            .ok_or(Error::NoteEncoding(oid))
+    }
+
+    pub fn write_note(&self, notes_ref: &str, rev: &str, data: &str) -> Result<()> {
+        let oid = self.find_commit(rev)?;
+        let signature = self.signature()?;
+        self.repo
+            .note(&signature, &signature, Some(notes_ref), oid, data, true)?;
+        Ok(())
+    }
+
+    /// Returns the commits that have a note under the given ref.
+    pub fn list_notes(&self, notes_ref: &str) -> Result<Vec<String>> {
+        let notes = match self.repo.notes(Some(notes_ref)) {
+            Ok(notes) => notes,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        notes
+            .map(|note| {
+                note.map(|(_, commit)| commit.to_string())
+                    .map_err(From::from)
+            })
+            .collect()
+    }
+
+    fn find_commit(&self, rev: &str) -> Result<Oid> {
+        self.repo
+            .revparse_single(rev)
+            .and_then(|object| object.peel_to_commit())
+            .map(|commit| commit.id())
+            .map_err(|err| Error::Revision(rev.to_owned(), err))
+    }
+
+    fn signature(&self) -> Result<Signature<'static>> {
+        match self.repo.signature() {
+            Ok(signature) => Ok(signature),
+            // CI runners often don't configure a Git identity
+            Err(err) if err.code() == ErrorCode::NotFound => {
+                Signature::now("repometrics", "repometrics@localhost").map_err(From::from)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_util::{commit, test_repo};
+
+    #[test]
+    fn discover() {
+        let (dir, commits) = test_repo(&[1000]);
+        let subdir = dir.path().join("sub");
+        fs::create_dir(&subdir).unwrap();
+        let repo = Repository::discover(&subdir).unwrap();
+        assert_eq!(
+            repo.workdir().canonicalize().unwrap(),
+            dir.path().canonicalize().unwrap()
+        );
+        assert_eq!(repo.head_commit().unwrap(), commits[0]);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            Repository::discover(empty.path()),
+            Err(Error::NotFound(_))
+        ));
+
+        let bare = tempfile::tempdir().unwrap();
+        git2::Repository::init_bare(bare.path()).unwrap();
+        assert!(matches!(
+            Repository::discover(bare.path()),
+            Err(Error::Bare(_))
+        ));
+    }
+
+    #[test]
+    fn discover_worktree() {
+        let (dir, commits) = test_repo(&[1000, 2000]);
+        let repo = Repository::discover(dir.path()).unwrap();
+        let worktree = repo.add_worktree(&commits[0]).unwrap();
+        let worktree_repo = Repository::discover(worktree.path()).unwrap();
+        assert_eq!(worktree_repo.head_commit().unwrap(), commits[0]);
+        assert!(worktree_repo.is_clean().unwrap());
+    }
+
+    #[test]
+    fn add_worktree() {
+        let (dir, commits) = test_repo(&[4000, 5000]);
+        let repo = Repository::discover(dir.path()).unwrap();
+        let worktree = repo.add_worktree(&commits[0]).unwrap();
+        let path = worktree.path().to_owned();
+        assert!(path.exists());
+        let worktree_repo = git2::Repository::open(&path).unwrap();
+        assert!(worktree_repo.head_detached().unwrap());
+        assert_eq!(
+            worktree_repo.head().unwrap().target().unwrap().to_string(),
+            commits[0]
+        );
+        // the temporary branch is deleted after the worktree is created
+        let branches = repo.repo.branches(None).unwrap().count();
+        assert_eq!(branches, 1);
+        assert_eq!(repo.repo.worktrees().unwrap().len(), 1);
+
+        drop(worktree);
+        assert!(!path.exists());
+        assert!(repo.repo.worktrees().unwrap().is_empty());
+    }
+
+    #[test]
+    fn resolve() {
+        let (dir, commits) = test_repo(&[1000, 2000]);
+        let repo = Repository::discover(dir.path()).unwrap();
+        assert_eq!(repo.resolve_commit("HEAD~1").unwrap(), commits[0]);
+        assert!(matches!(
+            repo.resolve_commit("unknown"),
+            Err(Error::Revision(rev, _)) if rev == "unknown"
+        ));
+        assert_eq!(
+            repo.commit_time(&commits[0]),
+            DateTime::from_timestamp(1000, 0)
+        );
+        assert_eq!(repo.commit_time("unknown"), None);
+    }
+
+    #[test]
+    fn history() {
+        // c0 - c1 - merge
+        //   \- s1 -/
+        //   \- s2 (branch other)
+        //   \- u1 (no branch)
+        let (dir, commits) = test_repo(&[1000, 2000]);
+        let path = dir.path();
+        let s1 = commit(path, None, &[&commits[0]], 1500, &[("s1", "")]);
+        let merge = commit(path, Some("HEAD"), &[&commits[1], &s1], 3000, &[]);
+        let s2 = commit(path, Some("refs/heads/other"), &[&commits[0]], 1600, &[]);
+        let u1 = commit(path, None, &[&commits[0]], 1700, &[]);
+        let repo = Repository::discover(path).unwrap();
+
+        assert_eq!(
+            repo.ancestors("HEAD", 5).unwrap(),
+            [merge.clone(), commits[1].clone(), commits[0].clone()]
+        );
+        assert_eq!(
+            repo.ancestors("HEAD", 1).unwrap(),
+            [merge, commits[1].clone()]
+        );
+        assert_eq!(repo.merge_base("other").unwrap(), commits[0]);
+        assert!(matches!(
+            repo.merge_base("unknown"),
+            Err(Error::Revision(_, _))
+        ));
+
+        assert!(repo.is_reachable(&s1).unwrap());
+        assert!(repo.is_reachable(&s2).unwrap());
+        assert!(repo.is_reachable(&commits[0]).unwrap());
+        assert!(!repo.is_reachable(&u1).unwrap());
+        assert!(!repo.is_reachable("unknown").unwrap());
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    env,
+    io::{Cursor, Read as _},
+};
+
+use anyhow::{Context as _, Result};
+use log::{debug, info, warn};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    config::GithubConfig,
+    storage::{Backend, Loaded},
+};
+
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Deserialize)]
+struct WorkflowRuns {
+    workflow_runs: Vec<WorkflowRun>,
+}
+
+#[derive(Deserialize)]
+struct WorkflowRun {
+    id: u64,
+    name: Option<String>,
+    path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Artifacts {
+    artifacts: Vec<Artifact>,
+}
+
+#[derive(Deserialize)]
+struct Artifact {
+    id: u64,
+    name: String,
+    #[serde(default)]
+    expired: bool,
+}
+
+/// Client for the GitHub Actions API, also used for Forgejo and Gitea which provide the same
+/// endpoints.
+pub struct Api<'a> {
+    client: Client,
+    token: String,
+    config: &'a GithubConfig,
+}
+
+impl<'a> Api<'a> {
+    pub fn new(config: &'a GithubConfig) -> Result<Self> {
+        let token = env::var("GITHUB_TOKEN")
+            .context("missing GitHub API access token -- set GITHUB_TOKEN")?;
+        let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .context("failed to create GitHub API client")?;
+        Ok(Self {
+            client,
+            token,
+            config,
+        })
+    }
+
+    pub fn get_artifact(&self, commit: &str) -> Result<Option<String>> {
+        debug!(
+            "Searching artifacts for commit {commit} in GitHub repository {} on {}",
+            self.config.repository, self.config.api
+        );
+        let runs = self.get_runs_for_commit(commit)?;
+        if runs.len() > 1 {
+            warn!(
+                "Found multiple matching workflow runs for commit {commit} in GitHub repository {} on {}, using the newest one with a matching artifact",
+                self.config.repository, self.config.api
+            );
+        }
+        let mut artifacts = Vec::new();
+        for run in runs {
+            artifacts = self.get_artifacts_for_run(run)?;
+            if !artifacts.is_empty() {
+                break;
+            }
+        }
+        let Some(artifact) = artifacts.into_iter().next() else {
+            debug!("No matching artifacts found for commit {commit} on GitHub");
+            return Ok(None);
+        };
+        let data = self.get_file_from_artifact(artifact)?;
+        info!(
+            "Fetched metrics for commit {commit} from GitHub repository {} on {}",
+            self.config.repository, self.config.api
+        );
+        Ok(Some(data))
+    }
+
+    /// Returns the successful workflow runs for a commit, newest first.
+    fn get_runs_for_commit(&self, commit: &str) -> Result<Vec<u64>> {
+        debug!("Fetching workflow runs for commit {commit}");
+        let runs: WorkflowRuns = self.get_json(
+            "actions/runs",
+            &[
+                ("head_sha", commit),
+                ("status", "success"),
+                ("per_page", "100"),
+            ],
+        )?;
+        Ok(filter_runs(runs, self.config.workflow.as_deref()))
+    }
+
+    fn get_artifacts_for_run(&self, run: u64) -> Result<Vec<u64>> {
+        debug!("Fetching artifacts for workflow run {run}");
+        let artifacts: Artifacts = self.get_json(
+            &format!("actions/runs/{run}/artifacts"),
+            &[("name", &self.config.artifact)],
+        )?;
+        Ok(filter_artifacts(artifacts, &self.config.artifact))
+    }
+
+    fn get_file_from_artifact(&self, artifact: u64) -> Result<String> {
+        debug!(
+            "Fetching file {} from artifact {artifact}",
+            self.config.file
+        );
+        let data = self
+            .get(&format!("actions/artifacts/{artifact}/zip"))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.bytes())
+            .context("failed to download artifact from GitHub")?;
+        read_file(&data, &self.config.file)
+    }
+
+    fn get(&self, endpoint: &str) -> RequestBuilder {
+        let url = format!(
+            "{}/repos/{}/{endpoint}",
+            self.config.api.trim_end_matches('/'),
+            self.config.repository
+        );
+        self.client
+            .get(url)
+            .bearer_auth(&self.token)
+            .header("accept", "application/vnd.github+json")
+            .header("x-github-api-version", "2022-11-28")
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, endpoint: &str, query: &[(&str, &str)]) -> Result<T> {
+        self.get(endpoint)
+            .query(query)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("failed to fetch {endpoint} from GitHub"))?
+            .json()
+            .with_context(|| format!("failed to parse {endpoint} returned by GitHub"))
+    }
+}
+
+/// Returns the IDs of the runs of the workflow, matched by name or file path, or of all runs if
+/// no workflow is configured.
+fn filter_runs(runs: WorkflowRuns, workflow: Option<&str>) -> Vec<u64> {
+    runs.workflow_runs
+        .into_iter()
+        .filter(|run| {
+            let Some(workflow) = workflow else {
+                return true;
+            };
+            run.name.as_deref() == Some(workflow)
+                || run
+                    .path
+                    .as_ref()
+                    .is_some_and(|path| path.ends_with(workflow))
+        })
+        .map(|run| run.id)
+        .collect()
+}
+
+/// Returns the IDs of the unexpired artifacts with the given name.
+fn filter_artifacts(artifacts: Artifacts, name: &str) -> Vec<u64> {
+    artifacts
+        .artifacts
+        .into_iter()
+        .filter(|artifact| artifact.name == name && !artifact.expired)
+        .map(|artifact| artifact.id)
+        .collect()
+}
+
+/// Reads a file from a zip archive.
+fn read_file(data: &[u8], name: &str) -> Result<String> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(data)).context("failed to open artifact archive")?;
+    let mut file = archive
+        .by_name(name)
+        .with_context(|| format!("artifact does not contain the file {name}"))?;
+    let mut s = String::new();
+    file.read_to_string(&mut s)
+        .context("failed to read file from artifact archive")?;
+    Ok(s)
+}
+
+impl Backend for GithubConfig {
+    fn name(&self) -> String {
+        format!("GitHub repository {} on {}", self.repository, self.api)
+    }
+
+    fn load(&self, commit: &str) -> Result<Option<Loaded>> {
+        self.api()?
+            .get_artifact(commit)
+            .map(|data| data.map(From::from))
+            .context("failed to retrieve metrics from GitHub")
+    }
+
+    fn store(&self, _commit: &str, _data: &str) -> Result<()> {
+        anyhow::bail!("storing metrics is not supported for GitHub")
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        anyhow::bail!("listing commits is not supported for GitHub")
+    }
+
+    fn can_store(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+
+    use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+    use super::*;
+
+    #[test]
+    fn select_runs() {
+        let runs = || -> WorkflowRuns {
+            serde_json::from_str(
+                r#"{"workflow_runs": [
+                    {"id": 3, "name": "CI", "path": ".github/workflows/ci.yml"},
+                    {"id": 2, "name": "Metrics", "path": ".github/workflows/metrics.yml"},
+                    {"id": 1, "name": null, "path": null}
+                ]}"#,
+            )
+            .unwrap()
+        };
+        assert_eq!(filter_runs(runs(), None), [3, 2, 1]);
+        assert_eq!(filter_runs(runs(), Some("Metrics")), [2]);
+        assert_eq!(filter_runs(runs(), Some("ci.yml")), [3]);
+        assert!(filter_runs(runs(), Some("Release")).is_empty());
+    }
+
+    #[test]
+    fn select_artifacts() {
+        let artifacts: Artifacts = serde_json::from_str(
+            r#"{"artifacts": [
+                {"id": 4, "name": "metrics", "expired": true},
+                {"id": 3, "name": "metrics-old"},
+                {"id": 2, "name": "metrics", "expired": false},
+                {"id": 1, "name": "metrics"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(filter_artifacts(artifacts, "metrics"), [2, 1]);
+    }
+
+    #[test]
+    fn read_artifact() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        writer.start_file("other.txt", options).unwrap();
+        writer.write_all(b"other").unwrap();
+        writer.start_file("metrics.toml", options).unwrap();
+        writer.write_all(b"a = 1").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        assert_eq!(read_file(&data, "metrics.toml").unwrap(), "a = 1");
+        let error = read_file(&data, "missing.toml").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "artifact does not contain the file missing.toml"
+        );
+        assert!(read_file(b"not a zip file", "metrics.toml").is_err());
+    }
+}
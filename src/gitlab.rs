@@ -4,20 +4,29 @@ use anyhow::{Context as _, Result};
 use gitlab::{
     api::{
         self,
-        common::NameOrId,
+        common::{NameOrId, SortOrder},
         endpoint_prelude::Method,
         projects::{
             jobs::JobScope,
-            pipelines::{PipelineJobs, Pipelines},
+            packages::{
+                generic::{GetPackageFile, UploadPackageFile},
+                Packages,
+            },
+            pipelines::{PipelineJobs, PipelineOrderBy, Pipelines},
         },
-        Endpoint, Pagination, Query as _,
+        ApiError, Endpoint, Pagination, Query as _,
     },
     Gitlab,
 };
 use log::{debug, info, warn};
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+use crate::{
+    config::GitlabConfig,
+    storage::{Backend, Loaded},
+};
+
+#[derive(Clone, Copy, Deserialize)]
 #[serde(transparent)]
 struct JobId(u64);
 
@@ -29,74 +38,147 @@ struct PipelineId(u64);
 struct Job {
     id: JobId,
     name: String,
+    web_url: String,
 }
 
 #[derive(Deserialize)]
 struct Pipeline {
     id: PipelineId,
+    source: String,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    version: String,
 }
 
+const PACKAGE_FILE: &str = "metrics.toml";
+
 pub struct Api<'a> {
     gitlab: Gitlab,
     host: &'a str,
     project: &'a str,
     job: &'a str,
     artifact: &'a str,
+    package: Option<&'a str>,
+    ref_: Option<&'a str>,
+    source: Option<&'a str>,
 }
 
 impl<'a> Api<'a> {
-    pub fn new(host: &'a str, project: &'a str, job: &'a str, artifact: &'a str) -> Result<Self> {
+    pub fn new(config: &'a GitlabConfig) -> Result<Self> {
         let token = env::var("GITLAB_API_TOKEN")
             .context("missing Gitlab API access token -- set GITLAB_API_TOKEN")?;
-        let gitlab = Gitlab::new(host, token).context("failed to create Gitlab API instance")?;
+        let gitlab =
+            Gitlab::new(&config.host, token).context("failed to create Gitlab API instance")?;
         Ok(Self {
             gitlab,
-            host,
-            project,
-            job,
-            artifact,
+            host: &config.host,
+            project: &config.project,
+            job: &config.job,
+            artifact: &config.artifact,
+            package: config.package.as_deref(),
+            ref_: config.ref_.as_deref(),
+            source: config.source.as_deref(),
         })
     }
 
-    pub fn get_artifact(&self, commit: &str) -> Result<String> {
+    pub fn get_values(&self, commit: &str) -> Result<Option<Loaded>> {
+        let artifact_err = match self.get_artifact(commit) {
+            Ok(Some(artifact)) => return Ok(Some(artifact)),
+            Ok(None) => None,
+            Err(err) => Some(err),
+        };
+        let Some(package) = self.package else {
+            return artifact_err.map_or(Ok(None), Err);
+        };
+        if let Some(err) = &artifact_err {
+            debug!("Failed to fetch artifact for commit {commit}: {err:#}");
+        }
+        match self.get_package_file(package, commit)? {
+            Some(data) => Ok(Some(Loaded {
+                data,
+                source: Some(format!("Gitlab package {package}")),
+            })),
+            None => artifact_err.map_or(Ok(None), Err),
+        }
+    }
+
+    /// Returns the artifact from the newest matching job together with the pipeline and job ID,
+    /// or `None` if there is no matching job.
+    pub fn get_artifact(&self, commit: &str) -> Result<Option<Loaded>> {
         debug!(
             "Searching artifacts for commit {commit} in Gitlab project {} on {}",
             self.project, self.host
         );
+        // pipelines are sorted newest first, so the first pipeline with a matching job wins
         let pipelines = self.get_pipelines_for_commit(commit)?;
-        let mut jobs = Vec::new();
         for pipeline in pipelines {
-            jobs.extend(self.get_jobs_for_pipeline(pipeline)?);
+            let mut jobs = self.get_jobs_for_pipeline(&pipeline.id)?;
+            jobs.sort_by_key(|job| job.id.0);
+            let Some(job) = jobs.pop() else {
+                continue;
+            };
+            if !jobs.is_empty() {
+                let names: Vec<_> = jobs.iter().map(|job| job.name.as_str()).collect();
+                warn!(
+                    "Found multiple matching jobs in pipeline {} for commit {commit}, using the newest one ({}), ignoring: {}",
+                    pipeline.id.0,
+                    job.name,
+                    names.join(", ")
+                );
+            }
+            info!(
+                "Using artifact from job {} ({}) in pipeline {} for commit {commit}: {}",
+                job.name, job.id.0, pipeline.id.0, job.web_url
+            );
+            let artifact = self.get_artifact_for_job(job.id)?;
+            info!(
+                "Fetched metrics for commit {commit} from Gitlab project {} on {}",
+                self.project, self.host
+            );
+            return Ok(Some(Loaded {
+                data: artifact,
+                source: Some(format!(
+                    "Gitlab pipeline {}, job {}",
+                    pipeline.id.0, job.id.0
+                )),
+            }));
         }
-        if jobs.len() > 1 {
-            warn!("Found multiple matching jobs for commit {commit} in Gitlab project {} on {}, using the first one", self.project, self.host);
-        }
-        let job = jobs.pop().with_context(|| {
-            format!("no matching artifacts found for commit {commit} on Gitlab")
-        })?;
-        let artifact = self.get_artifact_for_job(job)?;
-        info!(
-            "Fetched metrics for commit {commit} from Gitlab project {} on {}",
-            self.project, self.host
-        );
-        Ok(artifact)
+        debug!("No matching artifacts found for commit {commit} on Gitlab");
+        Ok(None)
     }
 
-    fn get_pipelines_for_commit(&self, commit: &str) -> Result<Vec<PipelineId>> {
+    fn get_pipelines_for_commit(&self, commit: &str) -> Result<Vec<Pipeline>> {
         debug!("Fetching pipelines for commit {commit}");
-        let query = Pipelines::builder()
+        let mut builder = Pipelines::builder();
+        builder
             .project(self.project)
             .sha(commit)
+            .order_by(PipelineOrderBy::Id)
+            .sort(SortOrder::Descending);
+        if let Some(ref_) = self.ref_ {
+            builder.ref_(ref_);
+        }
+        let query = builder
             .build()
             .context("failed to fetch pipelines from Gitlab")?;
         api::paged(query, Pagination::All)
             .iter(&self.gitlab)
             .map(|result| result.context("failed to parse pipeline returned by Gitlab"))
-            .map(|result| result.map(|pipeline: Pipeline| pipeline.id))
+            .filter(|result| {
+                result
+                    .as_ref()
+                    .map(|pipeline: &Pipeline| {
+                        self.source.is_none_or(|source| pipeline.source == source)
+                    })
+                    .unwrap_or(true)
+            })
             .collect()
     }
 
-    fn get_jobs_for_pipeline(&self, pipeline: PipelineId) -> Result<Vec<JobId>> {
+    fn get_jobs_for_pipeline(&self, pipeline: &PipelineId) -> Result<Vec<Job>> {
         debug!("Fetching jobs for pipeline {}", pipeline.0);
         let query = PipelineJobs::builder()
             .project(self.project)
@@ -110,10 +192,9 @@ impl<'a> Api<'a> {
             .filter(|result| {
                 result
                     .as_ref()
-                    .map(|job: &Job| job.name == self.job)
+                    .map(|job: &Job| matches_pattern(self.job, &job.name))
                     .unwrap_or(true)
             })
-            .map(|result| result.map(|job| job.id))
             .collect()
     }
 
@@ -129,6 +210,144 @@ impl<'a> Api<'a> {
             .context("failed to fetch artifact from Gitlab")?;
         String::from_utf8(data).context("failed to decode artifact returned by Gitlab as UTF-8")
     }
+
+    fn get_package_file(&self, package: &str, commit: &str) -> Result<Option<String>> {
+        debug!("Fetching package {package} version {commit} from Gitlab");
+        let query = GetPackageFile::builder()
+            .project(self.project)
+            .package_name(package)
+            .package_version(commit)
+            .file_name(PACKAGE_FILE)
+            .build()
+            .context("failed to fetch package from Gitlab")?;
+        let data = match api::raw(query).query(&self.gitlab) {
+            Ok(data) => data,
+            Err(err) if is_not_found(&err) => {
+                debug!("No package {package} version {commit} found on Gitlab");
+                return Ok(None);
+            }
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("failed to fetch package {package} version {commit} from Gitlab")
+                })
+            }
+        };
+        info!(
+            "Fetched metrics for commit {commit} from package {package} in Gitlab project {} on {}",
+            self.project, self.host
+        );
+        String::from_utf8(data)
+            .map(Some)
+            .context("failed to decode package returned by Gitlab as UTF-8")
+    }
+
+    pub fn upload_package(&self, commit: &str, data: &str) -> Result<()> {
+        let package = self
+            .package
+            .context("no Gitlab package configured for uploading metrics")?;
+        debug!("Uploading package {package} version {commit} to Gitlab");
+        let query = UploadPackageFile::builder()
+            .project(self.project)
+            .package_name(package)
+            .package_version(commit)
+            .file_name(PACKAGE_FILE)
+            .contents(data.as_bytes())
+            .build()
+            .context("failed to upload package to Gitlab")?;
+        api::ignore(query).query(&self.gitlab).with_context(|| {
+            format!("failed to upload package {package} version {commit} to Gitlab")
+        })?;
+        info!(
+            "Uploaded metrics for commit {commit} to package {package} in Gitlab project {} on {}",
+            self.project, self.host
+        );
+        Ok(())
+    }
+
+    pub fn list_packages(&self) -> Result<Vec<String>> {
+        let package = self
+            .package
+            .context("listing commits is only supported for Gitlab packages")?;
+        let query = Packages::builder()
+            .project(self.project)
+            .package_name(package)
+            .build()
+            .context("failed to fetch packages from Gitlab")?;
+        api::paged(query, Pagination::All)
+            .iter(&self.gitlab)
+            .map(|result| result.context("failed to parse package returned by Gitlab"))
+            .filter(|result| {
+                result
+                    .as_ref()
+                    .map(|p: &Package| p.name == package)
+                    .unwrap_or(true)
+            })
+            .map(|result| result.map(|p| p.version))
+            .collect()
+    }
+}
+
+impl Backend for GitlabConfig {
+    fn name(&self) -> String {
+        format!("Gitlab project {} on {}", self.project, self.host)
+    }
+
+    fn load(&self, commit: &str) -> Result<Option<Loaded>> {
+        self.api()?
+            .get_values(commit)
+            .context("failed to retrieve metrics from Gitlab")
+    }
+
+    fn store(&self, commit: &str, data: &str) -> Result<()> {
+        self.api()?.upload_package(commit, data)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        self.api()?.list_packages()
+    }
+
+    fn can_store(&self) -> bool {
+        self.package.is_some()
+    }
+}
+
+/// Returns true if Gitlab responded with 404 Not Found.
+fn is_not_found<E>(err: &ApiError<E>) -> bool
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    match err {
+        ApiError::GitlabService { status, .. }
+        | ApiError::GitlabWithStatus { status, .. }
+        | ApiError::GitlabObjectWithStatus { status, .. }
+        | ApiError::GitlabUnrecognizedWithStatus { status, .. } => status.as_u16() == 404,
+        _ => false,
+    }
+}
+
+/// Matches a job name against a pattern where `*` matches any sequence of characters.
+///
+/// Jobs generated with `parallel:matrix` are named like `build: [nk3xn, test]`, so they can be
+/// selected either with their full name or with a pattern like `build: [nk3xn, *]`.
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(prefix) = parts.next() else {
+        return pattern == name;
+    };
+    let Some(mut rest) = name.strip_prefix(prefix) else {
+        return false;
+    };
+    let mut parts: Vec<_> = parts.collect();
+    let Some(suffix) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        let Some(i) = rest.find(part) else {
+            return false;
+        };
+        rest = &rest[i + part.len()..];
+    }
+    rest.ends_with(suffix)
 }
 
 struct JobArtifact<'a> {
@@ -150,3 +369,28 @@ impl Endpoint for JobArtifact<'_> {
         .into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::matches_pattern;
+
+    #[test]
+    fn job_patterns() {
+        assert!(matches_pattern("build", "build"));
+        assert!(!matches_pattern("build", "build: [nk3xn, test]"));
+        assert!(matches_pattern(
+            "build: [nk3xn, test]",
+            "build: [nk3xn, test]"
+        ));
+        assert!(matches_pattern("build: [nk3xn, *]", "build: [nk3xn, test]"));
+        assert!(!matches_pattern(
+            "build: [nk3am, *]",
+            "build: [nk3xn, test]"
+        ));
+        assert!(matches_pattern("build*", "build: [nk3xn, test]"));
+        assert!(matches_pattern("*: [*, test]", "build: [nk3xn, test]"));
+        assert!(!matches_pattern("*: [*, test]", "build: [nk3xn, release]"));
+        assert!(matches_pattern("*", "metrics"));
+        assert!(!matches_pattern("a*b*b", "ab"));
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result};
+use chrono::Utc;
+use clap::ValueEnum;
+use log::{debug, info};
+use rusqlite::{params, Connection, OptionalExtension as _};
+
+use crate::{
+    cache,
+    config::{Config, StorageConfig},
+    data::Values,
+    storage::{Backend, Loaded},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    commit_id TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    branch TEXT,
+    host TEXT,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS runs_commit_id ON runs (commit_id);
+CREATE TABLE IF NOT EXISTS run_values (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    metric TEXT NOT NULL,
+    value INTEGER NOT NULL,
+    PRIMARY KEY (run_id, metric)
+);
+";
+
+/// A SQLite database that records every stored values file together with metadata about the
+/// run that generated it.
+pub struct Database {
+    path: PathBuf,
+    root: PathBuf,
+}
+
+impl Database {
+    pub fn new(path: &Path, root: &Path) -> Self {
+        Self {
+            path: path.to_owned(),
+            root: root.to_owned(),
+        }
+    }
+
+    /// Creates a database for the path set on the command line or the first SQLite storage
+    /// backend in the configuration.
+    pub fn from_config(config: &Config, path: Option<PathBuf>, root: &Path) -> Result<Self> {
+        let path = path
+            .or_else(|| {
+                config.storage.iter().flatten().find_map(|storage| {
+                    if let StorageConfig::Sqlite(config) = storage {
+                        Some(config.path.clone())
+                    } else {
+                        None
+                    }
+                })
+            })
+            .context(
+                "no SQLite database configured -- set --database or add a sqlite storage backend",
+            )?;
+        Ok(Self::new(&path, root))
+    }
+
+    fn connect(&self) -> Result<Connection> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() && !parent.exists() {
+                fs::create_dir_all(parent).with_context(|| {
+                    format!("failed to create directory '{}'", parent.display())
+                })?;
+            }
+        }
+        debug!("Opening SQLite database '{}'", self.path.display());
+        let connection = Connection::open(&self.path)
+            .with_context(|| format!("failed to open SQLite database '{}'", self.path.display()))?;
+        connection
+            .execute_batch(SCHEMA)
+            .context("failed to create SQLite database schema")?;
+        Ok(connection)
+    }
+
+    /// Returns the most recent runs, at most one per commit, optionally restricted to a branch.
+    pub fn query(&self, branch: Option<&str>, limit: usize) -> Result<Vec<Run>> {
+        let connection = self.connect()?;
+        let mut statement = connection
+            .prepare(
+                "SELECT id, commit_id, timestamp, branch, host FROM runs
+                 WHERE id IN (
+                     SELECT MAX(id) FROM runs WHERE (?1 IS NULL OR branch = ?1) GROUP BY commit_id
+                 )
+                 ORDER BY id DESC LIMIT ?2",
+            )
+            .context("failed to prepare history query")?;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let mut runs = statement
+            .query_map(params![branch, limit], |row| {
+                Ok(Run {
+                    id: row.get(0)?,
+                    commit: row.get(1)?,
+                    timestamp: row.get(2)?,
+                    branch: row.get(3)?,
+                    host: row.get(4)?,
+                    values: BTreeMap::new(),
+                })
+            })
+            .context("failed to query history")?
+            .collect::<Result<Vec<_>, _>>()
+            .context("failed to read history")?;
+        let mut statement = connection
+            .prepare("SELECT metric, value FROM run_values WHERE run_id = ?1")
+            .context("failed to prepare values query")?;
+        for run in &mut runs {
+            run.values = statement
+                .query_map([run.id], |row| {
+                    let value: i64 = row.get(1)?;
+                    Ok((row.get(0)?, usize::try_from(value).unwrap_or_default()))
+                })
+                .context("failed to query values")?
+                .collect::<Result<_, _>>()
+                .context("failed to read values")?;
+        }
+        Ok(runs)
+    }
+
+    fn insert(&self, commit: &str, data: &str, branch: Option<&str>) -> Result<()> {
+        let values: Values = toml::from_str(data).context("failed to parse values")?;
+        let mut connection = self.connect()?;
+        let transaction = connection
+            .transaction()
+            .context("failed to start SQLite transaction")?;
+        transaction
+            .execute(
+                "INSERT INTO runs (commit_id, timestamp, branch, host, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![commit, Utc::now().to_rfc3339(), branch, get_host(), data],
+            )
+            .context("failed to insert run into SQLite database")?;
+        let run_id = transaction.last_insert_rowid();
+        for (metric, value) in values.iter() {
+            let value = i64::try_from(value).unwrap_or(i64::MAX);
+            transaction
+                .execute(
+                    "INSERT INTO run_values (run_id, metric, value) VALUES (?1, ?2, ?3)",
+                    params![run_id, metric, value],
+                )
+                .context("failed to insert values into SQLite database")?;
+        }
+        transaction
+            .commit()
+            .context("failed to commit SQLite transaction")
+    }
+}
+
+impl Backend for Database {
+    fn name(&self) -> String {
+        format!("SQLite database '{}'", self.path.display())
+    }
+
+    fn load(&self, commit: &str) -> Result<Option<Loaded>> {
+        let connection = self.connect()?;
+        let data: Option<String> = connection
+            .query_row(
+                "SELECT data FROM runs WHERE commit_id = ?1 ORDER BY id DESC LIMIT 1",
+                [commit],
+                |row| row.get(0),
+            )
+            .optional()
+            .context("failed to query SQLite database")?;
+        if data.is_some() {
+            info!("Read values for commit {commit} from {}", self.name());
+        }
+        Ok(data.map(Loaded::from))
+    }
+
+    fn store(&self, commit: &str, data: &str) -> Result<()> {
+        self.insert(commit, data, cache::get_branch(&self.root).as_deref())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        let connection = self.connect()?;
+        let mut statement = connection
+            .prepare("SELECT DISTINCT commit_id FROM runs ORDER BY commit_id")
+            .context("failed to prepare commit query")?;
+        let commits = statement
+            .query_map([], |row| row.get(0))
+            .context("failed to query commits")?
+            .collect::<Result<_, _>>()
+            .context("failed to read commits")?;
+        Ok(commits)
+    }
+}
+
+#[derive(Debug)]
+pub struct Run {
+    id: i64,
+    pub commit: String,
+    pub timestamp: String,
+    pub branch: Option<String>,
+    pub host: Option<String>,
+    pub values: BTreeMap<String, usize>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Aggregate {
+    Median,
+    Mean,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    /// Aggregates the values of each metric over all runs that contain it.
+    pub fn apply(&self, runs: &[Run]) -> Values {
+        let mut metrics: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for run in runs {
+            for (metric, value) in &run.values {
+                metrics.entry(metric).or_default().push(*value);
+            }
+        }
+        let values = metrics
+            .into_iter()
+            .map(|(metric, mut values)| {
+                values.sort_unstable();
+                (metric.to_owned(), self.aggregate(&values))
+            })
+            .collect();
+        Values::new(values)
+    }
+
+    /// Aggregates a sorted, non-empty list of values.
+    fn aggregate(&self, values: &[usize]) -> usize {
+        match self {
+            Self::Median => {
+                let mid = values.len() / 2;
+                if values.len().is_multiple_of(2) {
+                    values[mid - 1] + (values[mid] - values[mid - 1]) / 2
+                } else {
+                    values[mid]
+                }
+            }
+            Self::Mean => {
+                let sum: u128 = values.iter().map(|value| *value as u128).sum();
+                (sum / values.len() as u128) as usize
+            }
+            Self::Min => values[0],
+            Self::Max => values[values.len() - 1],
+        }
+    }
+}
+
+fn get_host() -> Option<String> {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_owned())
+        .filter(|host| !host.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(values: &[(&str, usize)]) -> Run {
+        Run {
+            id: 0,
+            commit: String::new(),
+            timestamp: String::new(),
+            branch: None,
+            host: None,
+            values: values
+                .iter()
+                .map(|(metric, value)| (metric.to_string(), *value))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn aggregate() {
+        let runs = [
+            run(&[("a", 10), ("b", 1)]),
+            run(&[("a", 30)]),
+            run(&[("a", 20), ("b", 4)]),
+        ];
+        let get = |aggregate: Aggregate| {
+            let values = aggregate.apply(&runs);
+            (values.get("a"), values.get("b"))
+        };
+        assert_eq!(get(Aggregate::Median), (Some(20), Some(2)));
+        assert_eq!(get(Aggregate::Mean), (Some(20), Some(2)));
+        assert_eq!(get(Aggregate::Min), (Some(10), Some(1)));
+        assert_eq!(get(Aggregate::Max), (Some(30), Some(4)));
+    }
+
+    #[test]
+    fn store_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::new(&dir.path().join("history.db"), Path::new("."));
+        let data = |value: usize| format!("version = \"1\"\n\n[values]\na = {value}\n");
+        database.store("c1", &data(1)).unwrap();
+        database.store("c2", &data(2)).unwrap();
+        database.store("c2", &data(3)).unwrap();
+
+        assert_eq!(database.list().unwrap(), ["c1", "c2"]);
+        let load = |commit| database.load(commit).unwrap().map(|loaded| loaded.data);
+        assert_eq!(load("c2"), Some(data(3)));
+        assert_eq!(load("c3"), None);
+
+        let runs = database.query(None, 10).unwrap();
+        let commits: Vec<_> = runs.iter().map(|run| run.commit.as_str()).collect();
+        assert_eq!(commits, ["c2", "c1"]);
+        assert_eq!(runs[0].values["a"], 3);
+        assert_eq!(database.query(None, 1).unwrap().len(), 1);
+        assert!(database.query(Some("unknown"), 10).unwrap().is_empty());
+
+        // the newest run of c3 was recorded on another branch
+        database.insert("c3", &data(4), Some("main")).unwrap();
+        database.insert("c4", &data(5), Some("main")).unwrap();
+        database.insert("c3", &data(6), Some("feature")).unwrap();
+        let runs = database.query(Some("main"), 10).unwrap();
+        let commits: Vec<_> = runs.iter().map(|run| run.commit.as_str()).collect();
+        assert_eq!(commits, ["c4", "c3"]);
+        assert_eq!(runs[1].values["a"], 4);
+    }
+}
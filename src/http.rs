@@ -0,0 +1,122 @@
+use std::env;
+
+use anyhow::{Context as _, Result};
+use log::{debug, info};
+use reqwest::{
+    blocking::{Client, RequestBuilder},
+    StatusCode,
+};
+
+use crate::{
+    config::HttpConfig,
+    storage::{Backend, Loaded},
+};
+
+/// A generic HTTP server that provides the values files under a URL that is generated from a
+/// template, for example `https://ci.example/{project}/{commit}/metrics.toml`.
+pub struct Http {
+    config: HttpConfig,
+    client: Client,
+}
+
+impl Http {
+    pub fn new(config: &HttpConfig) -> Self {
+        Self {
+            config: config.clone(),
+            client: Client::new(),
+        }
+    }
+
+    fn url(&self, commit: &str) -> Result<String> {
+        let mut url = self.config.url.clone();
+        if url.contains("{project}") {
+            let project = self.config.project.as_deref().with_context(|| {
+                format!(
+                    "HTTP URL template '{}' uses {{project}} but no project is set",
+                    url
+                )
+            })?;
+            url = url.replace("{project}", project);
+        }
+        Ok(url.replace("{commit}", commit))
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        if let Some(token_env) = &self.config.token_env {
+            let token = env::var(token_env)
+                .with_context(|| format!("missing HTTP access token -- set {token_env}"))?;
+            Ok(request.bearer_auth(token))
+        } else {
+            Ok(request)
+        }
+    }
+}
+
+impl Backend for Http {
+    fn name(&self) -> String {
+        format!("HTTP server {}", self.config.url)
+    }
+
+    fn load(&self, commit: &str) -> Result<Option<Loaded>> {
+        let url = self.url(commit)?;
+        debug!("Fetching values from {url}");
+        let response = self
+            .authorize(self.client.get(&url))?
+            .send()
+            .with_context(|| format!("failed to fetch {url}"))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let s = response
+            .error_for_status()
+            .and_then(|response| response.text())
+            .with_context(|| format!("failed to fetch {url}"))?;
+        info!("Fetched metrics for commit {commit} from {url}");
+        Ok(Some(s.into()))
+    }
+
+    fn store(&self, commit: &str, data: &str) -> Result<()> {
+        let url = self.url(commit)?;
+        debug!("Uploading values to {url}");
+        self.authorize(self.client.put(&url))?
+            .body(data.to_owned())
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("failed to upload values to {url}"))?;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        anyhow::bail!("listing commits is not supported for HTTP servers")
+    }
+
+    fn can_store(&self) -> bool {
+        self.config.upload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url_template() {
+        let mut config = HttpConfig {
+            url: "https://ci.example/{project}/{commit}/metrics.toml".to_owned(),
+            project: Some("firmware".to_owned()),
+            token_env: None,
+            upload: false,
+        };
+        assert_eq!(
+            Http::new(&config).url("abc").unwrap(),
+            "https://ci.example/firmware/abc/metrics.toml"
+        );
+        config.project = None;
+        assert!(Http::new(&config).url("abc").is_err());
+        config.url = "https://ci.example/{commit}/metrics.toml".to_owned();
+        assert_eq!(
+            Http::new(&config).url("abc").unwrap(),
+            "https://ci.example/abc/metrics.toml"
+        );
+    }
+}
@@ -2,13 +2,25 @@ mod args;
 mod cache;
 mod config;
 mod data;
+mod directory;
+mod git;
+mod github;
 mod gitlab;
+#[cfg(feature = "sqlite")]
+mod history;
+mod http;
+mod notes;
 mod output;
+mod prepare;
+mod s3;
+mod storage;
+#[cfg(test)]
+mod test_util;
 
 use std::path::Path;
 
 use anyhow::{Context as _, Result};
-use log::{error, info};
+use log::{error, info, warn};
 
 fn main() -> Result<()> {
     env_logger::init();
@@ -17,83 +29,438 @@ fn main() -> Result<()> {
     let config = config::load(args.config, args.command.root())?;
 
     match args.command {
+        args::Command::Cache { root, command } => {
+            let root = root.as_deref().unwrap_or_else(|| ".".as_ref());
+            manage_cache(root, command)?;
+        }
         args::Command::Compare {
             baseline,
             test,
             compare_args,
+            #[cfg(feature = "sqlite")]
+            baseline_history,
         } => {
-            let metrics = config.metrics()?;
-            let baseline_values = data::Values::load(baseline)?;
+            #[cfg(feature = "sqlite")]
+            let history_values = load_history(&config, baseline_history, ".".as_ref())?;
+            #[cfg(not(feature = "sqlite"))]
+            let history_values: Option<(String, data::Values)> = None;
+            let (baseline, baseline_values) = if let Some((baseline, values)) = history_values {
+                (Some(baseline), values)
+            } else {
+                // clap ensures that either the baseline file or the history aggregate is set
+                let baseline = baseline.context("baseline file not set")?;
+                (None, data::Values::load(baseline)?)
+            };
             let test_values = data::Values::load(test)?;
-            let comparisons = metrics.compare(&config.defaults, &baseline_values, &test_values);
-            output::print_comparisons(compare_args.output_format, &comparisons);
+            let comparisons = if let Some(metrics) = &config.metrics {
+                metrics.compare(
+                    &config.defaults,
+                    &baseline_values,
+                    &test_values,
+                    compare_args.all_values,
+                )
+            } else {
+                info!("No metrics configured, comparing all values");
+                data::Metrics::default().compare(
+                    &config.defaults,
+                    &baseline_values,
+                    &test_values,
+                    true,
+                )
+            };
+            output::print_comparisons(
+                compare_args.output_format,
+                baseline.as_deref(),
+                &comparisons,
+            );
+            comparisons.check(&config.defaults, compare_args.strict)?;
         }
-        args::Command::Generate { cache, root } => {
+        args::Command::Generate {
+            cache,
+            store,
+            strict,
+            root,
+            gitlab,
+        } => {
             let metrics = config.metrics()?;
             let root = root.as_deref().unwrap_or_else(|| ".".as_ref());
-            let (_, formatted) = generate(metrics, root, cache)?;
+            let gitlab = gitlab.into_config()?;
+            let storage = store
+                .then(|| storage::Storage::new(&config, gitlab, root))
+                .transpose()?;
+            prepare::run(&config.prepare, root)?;
+            let (_, formatted) = generate(metrics, root, cache, storage.as_ref(), strict)?;
             print!("{}", formatted);
         }
-        args::Command::Load { root, rev, gitlab } => {
+        #[cfg(feature = "sqlite")]
+        args::Command::History {
+            root,
+            database,
+            branch,
+            limit,
+            aggregate,
+            metric,
+        } => {
             let root = root.as_deref().unwrap_or_else(|| ".".as_ref());
-            let gitlab = gitlab.into_config()?;
+            let database = history::Database::from_config(&config, database, root)?;
+            let runs = database.query(branch.as_deref(), limit)?;
+            if let Some(aggregate) = aggregate {
+                print!("{}", aggregate.apply(&runs).format()?);
+            } else {
+                for run in runs {
+                    print!(
+                        "{}\t{}\t{}\t{}\t",
+                        run.commit,
+                        run.timestamp,
+                        run.branch.as_deref().unwrap_or("-"),
+                        run.host.as_deref().unwrap_or("-"),
+                    );
+                    if let Some(metric) = &metric {
+                        if let Some(value) = run.values.get(metric) {
+                            println!("{value}");
+                        } else {
+                            println!("-");
+                        }
+                    } else {
+                        println!("{} values", run.values.len());
+                    }
+                }
+            }
+        }
+        args::Command::List { root, gitlab } => {
+            let root = root.as_deref().unwrap_or_else(|| ".".as_ref());
+            let storage = storage::Storage::new(&config, gitlab.into_config()?, root)?;
+            for backend in storage.backends() {
+                match backend.list() {
+                    Ok(commits) => {
+                        for commit in commits {
+                            println!("{commit}\t{}", backend.name());
+                        }
+                    }
+                    Err(err) => error!("Failed to list commits in {}: {:#}", backend.name(), err),
+                }
+            }
+        }
+        args::Command::Load {
+            root,
+            rev,
+            gitlab,
+            fallback_ancestors,
+        } => {
+            let root = root.as_deref().unwrap_or_else(|| ".".as_ref());
+            let storage = storage::Storage::new(&config, gitlab.into_config()?, root)?;
             let rev = cache::get_rev(root, rev.rev.as_deref(), rev.base.as_deref())?;
-            let s = load(&config, gitlab, root, &rev)?;
-            print!("{}", s)
+            let (commit, loaded) = load_nearest(&storage, root, &rev, fallback_ancestors)?;
+            if let Some(metrics) = &config.metrics {
+                let values = toml::from_str(&loaded.data).context("failed to parse values")?;
+                report_stale(metrics, &commit, &values);
+            }
+            print!("{}", loaded.data)
+        }
+        args::Command::Merge { files } => {
+            let values = files
+                .iter()
+                .map(data::Values::load)
+                .collect::<Result<Vec<_>>>()?;
+            let merged = data::Values::merge(values)?;
+            print!("{}", merged.format()?);
         }
         args::Command::Run {
             root,
             rev,
             gitlab,
+            fallback_ancestors,
             compare_args,
             cache,
+            store,
+            refresh_stale,
+            generate_baseline,
+            #[cfg(feature = "sqlite")]
+            baseline_history,
         } => {
             let metrics = config.metrics()?;
             let root = root.as_deref().unwrap_or_else(|| ".".as_ref());
-            let gitlab = gitlab.into_config()?;
-            let baseline_rev = cache::get_rev(root, rev.rev.as_deref(), rev.base.as_deref())?;
-            info!("Resolved baseline to commit {baseline_rev}");
-            let baseline_values = load(&config, gitlab, root, &baseline_rev)?;
-            let baseline_values = toml::from_str(&baseline_values)
-                .context("failed to parse cached baseline values")?;
-            let (values, _) = generate(metrics, root, cache)?;
-            let comparisons = metrics.compare(&config.defaults, &baseline_values, &values);
-            output::print_comparisons(compare_args.output_format, &comparisons);
+            let storage = storage::Storage::new(&config, gitlab.into_config()?, root)?;
+            #[cfg(feature = "sqlite")]
+            let history_values = {
+                anyhow::ensure!(
+                    baseline_history.aggregate.is_none()
+                        || (rev.rev.is_none() && rev.base.is_none()),
+                    "--baseline-history cannot be used with --rev or --base"
+                );
+                load_history(&config, baseline_history, root)?
+            };
+            #[cfg(not(feature = "sqlite"))]
+            let history_values = None;
+            let (baseline, baseline_values) = if let Some(history_values) = history_values {
+                history_values
+            } else {
+                let baseline_rev = cache::get_rev(root, rev.rev.as_deref(), rev.base.as_deref())?;
+                info!("Resolved baseline to commit {baseline_rev}");
+                let (commit, loaded) =
+                    match load_nearest(&storage, root, &baseline_rev, fallback_ancestors) {
+                        Ok(result) => result,
+                        Err(err) if generate_baseline => {
+                            warn!("Failed to load baseline values: {err:#}");
+                            let values = generate_in_worktree(
+                                metrics,
+                                &config.prepare,
+                                root,
+                                &baseline_rev,
+                            )?
+                            .format()?;
+                            if let Err(err) = cache::store_for_rev(root, &baseline_rev, &values) {
+                                error!("Failed to cache generated metrics: {}", err);
+                            }
+                            (baseline_rev.clone(), values.into())
+                        }
+                        Err(err) => return Err(err),
+                    };
+                let mut details = Vec::new();
+                if commit != baseline_rev {
+                    details.push(format!("nearest ancestor of {baseline_rev} with values"));
+                }
+                details.extend(loaded.source);
+                let baseline = if details.is_empty() {
+                    format!("commit {commit}")
+                } else {
+                    format!("commit {commit} ({})", details.join(", "))
+                };
+                let mut baseline_values: data::Values = toml::from_str(&loaded.data)
+                    .context("failed to parse cached baseline values")?;
+                let stale = report_stale(metrics, &commit, &baseline_values);
+                if !stale.is_empty() && refresh_stale {
+                    let stale_metrics = metrics.select(&stale);
+                    match generate_in_worktree(&stale_metrics, &config.prepare, root, &commit) {
+                        Ok(values) => {
+                            baseline_values.update(values);
+                            // cache the complete values so that they are not shadowed by a
+                            // cache file with only the regenerated metrics
+                            let formatted = baseline_values.format()?;
+                            if let Err(err) = cache::store_for_rev(root, &commit, &formatted) {
+                                error!("Failed to cache regenerated metrics: {}", err);
+                            }
+                        }
+                        Err(err) => warn!("Failed to regenerate baseline values: {err:#}"),
+                    }
+                }
+                (baseline, baseline_values)
+            };
+            prepare::run(&config.prepare, root)?;
+            // failures are reported in the comparison and checked afterwards
+            let (values, _) = generate(metrics, root, cache, store.then_some(&storage), false)?;
+            let comparisons = metrics.compare(
+                &config.defaults,
+                &baseline_values,
+                &values,
+                compare_args.all_values,
+            );
+            output::print_comparisons(compare_args.output_format, Some(&baseline), &comparisons);
+            comparisons.check(&config.defaults, compare_args.strict)?;
+        }
+        args::Command::FetchNotes { root, remote } => {
+            let root = root.as_deref().unwrap_or_else(|| ".".as_ref());
+            notes::fetch(root, &remote)?;
+        }
+        args::Command::PushNotes { root, remote } => {
+            let root = root.as_deref().unwrap_or_else(|| ".".as_ref());
+            notes::push(root, &remote)?;
         }
     }
 
     Ok(())
 }
 
-fn generate(metrics: &data::Metrics, root: &Path, cache: bool) -> Result<(data::Values, String)> {
+fn manage_cache(root: &Path, command: args::CacheCommand) -> Result<()> {
+    match command {
+        args::CacheCommand::List => {
+            for entry in cache::entries(root)? {
+                let time = entry
+                    .time
+                    .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "-".to_owned());
+                let metrics = entry
+                    .metrics
+                    .map(|metrics| metrics.to_string())
+                    .unwrap_or_else(|| "-".to_owned());
+                println!("{}\t{time}\t{metrics}", entry.commit);
+            }
+        }
+        args::CacheCommand::Show { rev } => {
+            let commit = cache::get_rev(root, Some(&rev), None)?;
+            let values = cache::load(root, &commit)?
+                .with_context(|| format!("no cached values for commit {commit}"))?;
+            print!("{values}");
+        }
+        args::CacheCommand::Prune {
+            older_than,
+            keep,
+            unreachable,
+        } => {
+            anyhow::ensure!(
+                older_than.is_some() || keep.is_some() || unreachable,
+                "no prune criteria set -- use --older-than, --keep or --unreachable"
+            );
+            let older_than =
+                older_than.map(|days| chrono::Utc::now() - chrono::Duration::days(days.into()));
+            let removed = cache::prune(root, older_than, keep, unreachable)?;
+            println!("Removed {} cache entries", removed.len());
+        }
+        args::CacheCommand::Clear => cache::clear(root)?,
+    }
+    Ok(())
+}
+
+/// Aggregates the baseline values from the history database if `--baseline-history` is set.
+#[cfg(feature = "sqlite")]
+fn load_history(
+    config: &config::Config,
+    args: args::BaselineHistory,
+    root: &Path,
+) -> Result<Option<(String, data::Values)>> {
+    let Some(aggregate) = args.aggregate else {
+        return Ok(None);
+    };
+    let database = history::Database::from_config(config, args.database, root)?;
+    let runs = database.query(args.branch.as_deref(), args.limit)?;
+    anyhow::ensure!(!runs.is_empty(), "no matching runs in the history database");
+    let baseline = format!(
+        "{:?} of {} commits from the history database",
+        aggregate,
+        runs.len()
+    );
+    info!("Using {baseline} as baseline");
+    Ok(Some((baseline, aggregate.apply(&runs))))
+}
+
+/// Loads the values for the commit or, if there are none, for the nearest of its ancestors up to
+/// `max_ancestors` commits back.
+fn load_nearest(
+    storage: &storage::Storage,
+    root: &Path,
+    commit: &str,
+    max_ancestors: usize,
+) -> Result<(String, storage::Loaded)> {
+    if max_ancestors == 0 {
+        return storage
+            .load(commit)
+            .map(|values| (commit.to_owned(), values));
+    }
+    let commits = cache::ancestors(root, commit, max_ancestors)?;
+    let (found, values) = storage.load_first(&commits)?;
+    if found != commit {
+        // printed unconditionally as the values are for a different commit than requested
+        eprintln!("No values found for commit {commit}, using ancestor {found} instead");
+    }
+    Ok((found, values))
+}
+
+/// Prints a notice if the values were generated with different metric definitions and returns
+/// the IDs of the stale metrics.
+fn report_stale<'a>(
+    metrics: &'a data::Metrics,
+    commit: &str,
+    values: &data::Values,
+) -> Vec<&'a str> {
+    let stale = metrics.stale(values);
+    if !stale.is_empty() {
+        eprintln!(
+            "Values for commit {commit} were generated with different definitions for metrics: {}",
+            stale.join(", ")
+        );
+    }
+    stale
+}
+
+/// Generates the values for a commit in a temporary worktree.
+fn generate_in_worktree(
+    metrics: &data::Metrics,
+    prepare: &config::PrepareConfig,
+    root: &Path,
+    commit: &str,
+) -> Result<data::Values> {
+    info!("Generating values for commit {commit} in a temporary worktree");
+    let repo = git::Repository::discover(root)?;
+    let worktree = repo.add_worktree(commit)?;
+    // the root can be a subdirectory of the repository
+    let root_dir = root
+        .canonicalize()
+        .with_context(|| format!("failed to resolve path '{}'", root.display()))?;
+    let workdir = repo
+        .workdir()
+        .canonicalize()
+        .with_context(|| format!("failed to resolve path '{}'", repo.workdir().display()))?;
+    let relative = root_dir.strip_prefix(&workdir).with_context(|| {
+        format!(
+            "root '{}' is not inside the working directory '{}'",
+            root_dir.display(),
+            workdir.display()
+        )
+    })?;
+    let worktree_root = worktree.path().join(relative);
+    prepare::run(prepare, &worktree_root)?;
+    Ok(metrics.generate(&worktree_root))
+}
+
+fn generate(
+    metrics: &data::Metrics,
+    root: &Path,
+    cache: bool,
+    storage: Option<&storage::Storage>,
+    strict: bool,
+) -> Result<(data::Values, String)> {
     let values = metrics.generate(root);
+    if strict {
+        let errors: Vec<_> = values.errors().map(|(metric, _)| metric).collect();
+        anyhow::ensure!(
+            errors.is_empty(),
+            "failed to generate metrics: {}",
+            errors.join(", ")
+        );
+    }
     let formatted = values.format()?;
     if cache {
         if let Err(err) = cache::store(root, &formatted) {
             error!("Failed to cache generated metrics: {}", err);
         }
     }
+    if let Some(storage) = storage {
+        storage.store(root, &formatted, cache)?;
+    }
     Ok((values, formatted))
 }
 
-fn load(
-    config: &config::Config,
-    gitlab: Option<config::GitlabConfig>,
-    root: &Path,
-    rev: &str,
-) -> Result<String> {
-    if let Some(values) = cache::load(root, rev)? {
-        return Ok(values);
-    }
-    if let Some(gitlab) = gitlab.as_ref().or(config.gitlab.as_ref()) {
-        let s = gitlab
-            .api()?
-            .get_artifact(rev)
-            .context("failed to retrieve metrics from Gitlab")?;
-        if let Err(err) = cache::store_for_rev(root, rev, &s) {
-            error!("Failed to cache downloaded metrics: {}", err);
-        }
-        return Ok(s);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{commit, test_repo};
+
+    #[test]
+    fn generate_baseline_in_worktree() {
+        let (dir, commits) = test_repo(&[6000]);
+        let path = dir.path();
+        let c1 = commit(
+            path,
+            Some("HEAD"),
+            &[&commits[0]],
+            6100,
+            &[("file", "1234")],
+        );
+        commit(path, Some("HEAD"), &[&c1], 6200, &[("file", "12")]);
+        let metrics: data::Metrics = toml::from_str(
+            r#"
+            [size]
+            type = "file-size"
+            input = "file"
+            "#,
+        )
+        .unwrap();
+
+        let values =
+            generate_in_worktree(&metrics, &config::PrepareConfig::default(), path, &c1).unwrap();
+        assert_eq!(values.get("size"), Some(4));
+        let repo = git2::Repository::open(path).unwrap();
+        assert!(repo.worktrees().unwrap().is_empty());
+        assert!(!path.join(".repometrics").exists());
     }
-    anyhow::bail!("Missing cache entry for {rev} and no Gitlab configuration")
 }
@@ -0,0 +1,128 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context as _, Result};
+use log::{debug, info};
+
+use crate::{
+    git,
+    storage::{Backend, Loaded},
+};
+
+const NOTES_REF: &str = "refs/notes/repometrics";
+
+/// Git notes under `refs/notes/repometrics` in the repository.
+pub struct Notes {
+    root: PathBuf,
+}
+
+impl Notes {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_owned(),
+        }
+    }
+}
+
+impl Backend for Notes {
+    fn name(&self) -> String {
+        format!("Git notes in '{}'", self.root.display())
+    }
+
+    fn load(&self, commit: &str) -> Result<Option<Loaded>> {
+        load(&self.root, commit).map(|data| data.map(From::from))
+    }
+
+    fn store(&self, commit: &str, data: &str) -> Result<()> {
+        store_for_rev(&self.root, commit, data)
+    }
+
+    fn list(&self) -> Result<Vec<String>> {
+        list(&self.root)
+    }
+}
+
+pub fn store_for_rev(path: &Path, rev: &str, data: &str) -> Result<()> {
+    info!("Writing data to Git note {NOTES_REF} for commit {rev}");
+    git::Repository::discover(path)?
+        .write_note(NOTES_REF, rev, data)
+        .context("failed to write Git note")
+}
+
+pub fn load(path: &Path, rev: &str) -> Result<Option<String>> {
+    let Some(repo) = discover(path)? else {
+        return Ok(None);
+    };
+    let note = repo
+        .read_note(NOTES_REF, rev)
+        .context("failed to read Git note")?;
+    if note.is_some() {
+        info!("Reading Git note {NOTES_REF} for commit {rev}");
+    } else {
+        debug!("No Git note {NOTES_REF} for commit {rev}");
+    }
+    Ok(note)
+}
+
+pub fn list(path: &Path) -> Result<Vec<String>> {
+    let Some(repo) = discover(path)? else {
+        return Ok(Vec::new());
+    };
+    repo.list_notes(NOTES_REF)
+        .context("failed to list Git notes")
+}
+
+fn discover(path: &Path) -> Result<Option<git::Repository>> {
+    match git::Repository::discover(path) {
+        Ok(repo) => Ok(Some(repo)),
+        Err(git::Error::NotFound(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// Fetching and pushing uses the git command so that the credential helpers and SSH configuration
+// of the user apply.
+
+pub fn fetch(path: &Path, remote: &str) -> Result<()> {
+    // Fetch into a separate ref and merge it so that local notes are not overwritten.
+    let remote_ref = format!("refs/notes/remotes/{remote}/repometrics");
+    info!("Fetching Git notes {NOTES_REF} from {remote}");
+    run(
+        path,
+        &["fetch", remote, &format!("+{NOTES_REF}:{remote_ref}")],
+    )?;
+    run(
+        path,
+        &[
+            "notes",
+            "--ref",
+            NOTES_REF,
+            "merge",
+            "--strategy=theirs",
+            &remote_ref,
+        ],
+    )
+}
+
+pub fn push(path: &Path, remote: &str) -> Result<()> {
+    info!("Pushing Git notes {NOTES_REF} to {remote}");
+    run(path, &["push", remote, NOTES_REF])
+}
+
+fn run(path: &Path, args: &[&str]) -> Result<()> {
+    let status = Command::new("git")
+        .current_dir(path)
+        .args(args)
+        .status()
+        .with_context(|| format!("failed to run git {}", args[0]))?;
+    anyhow::ensure!(
+        status.success(),
+        "running git {} failed with status code {} in '{}'",
+        args[0],
+        status,
+        path.display()
+    );
+    Ok(())
+}
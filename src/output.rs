@@ -1,11 +1,28 @@
 mod markdown;
 mod text;
 
-use crate::{args::OutputFormat, data::Comparisons};
+use std::collections::BTreeMap;
 
-pub fn print_comparisons(format: OutputFormat, comparisons: &Comparisons) {
+use crate::{
+    args::OutputFormat,
+    data::{Comparison, Comparisons},
+};
+
+pub fn print_comparisons(format: OutputFormat, baseline: Option<&str>, comparisons: &Comparisons) {
     match format {
-        OutputFormat::Text => text::print_comparisons(comparisons),
-        OutputFormat::Markdown => markdown::print_comparisons(comparisons),
+        OutputFormat::Text => text::print_comparisons(baseline, comparisons),
+        OutputFormat::Markdown => markdown::print_comparisons(baseline, comparisons),
+    }
+}
+
+/// Groups the comparisons by their metric group, starting with the ungrouped metrics.
+fn groups(comparisons: &[Comparison]) -> BTreeMap<Option<&str>, Vec<&Comparison>> {
+    let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for comparison in comparisons {
+        groups
+            .entry(comparison.group.as_deref())
+            .or_default()
+            .push(comparison);
     }
+    groups
 }
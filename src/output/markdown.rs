@@ -1,48 +1,124 @@
-use crate::data::{Comparison, Comparisons};
+use crate::data::{Comparison, Comparisons, Trend};
 
-pub fn print_comparisons(comparisons: &Comparisons) {
-    if comparisons.significant.is_empty() {
+pub fn print_comparisons(baseline: Option<&str>, comparisons: &Comparisons) {
+    if let Some(baseline) = baseline {
+        println!("Baseline: {baseline}");
+        println!();
+    }
+    if comparisons.is_unchanged() {
         println!("No significant changes.");
+    } else if comparisons.significant.is_empty() {
+        println!("No significant value changes.");
     } else {
         print_comparison_table(&comparisons.significant);
     }
 
-    if !comparisons.insignificant.is_empty() {
+    if !comparisons.failed.is_empty() {
         println!();
-        println!("<details>");
-        println!("<summary>Insignifcant changes</summary>");
+        println!("### Failed metrics");
         println!();
-        print_comparison_table(&comparisons.insignificant);
-        println!("</details>");
+        println!("| metric | baseline | test |");
+        println!("| ------ | -------- | ---- |");
+        for failure in &comparisons.failed {
+            let baseline = failure.baseline.as_deref().unwrap_or_default();
+            let test = failure.test.as_deref().unwrap_or_default();
+            println!(
+                "| {} | {} | {} |",
+                escape(&failure.metric),
+                escape(baseline),
+                escape(test)
+            );
+        }
+    }
+
+    for (title, comparisons) in [
+        ("Added metrics", &comparisons.added),
+        ("Removed metrics", &comparisons.removed),
+        ("Changed metric definitions", &comparisons.stale),
+    ] {
+        if !comparisons.is_empty() {
+            println!();
+            println!("### {title}");
+            println!();
+            print_comparison_table(comparisons);
+        }
+    }
+
+    for (title, comparisons) in [
+        ("Insignificant changes", &comparisons.insignificant),
+        ("Unchanged metrics", &comparisons.unchanged),
+    ] {
+        if !comparisons.is_empty() {
+            println!();
+            println!("<details>");
+            println!("<summary>{title}</summary>");
+            println!();
+            print_comparison_table(comparisons);
+            println!("</details>");
+        }
     }
 }
 
 fn print_comparison_table(comparisons: &[Comparison]) {
+    for (i, (group, comparisons)) in super::groups(comparisons).into_iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        if let Some(group) = group {
+            println!("**{group}**");
+            println!();
+        }
+        print_comparison_group(&comparisons);
+    }
+}
+
+fn print_comparison_group(comparisons: &[&Comparison]) {
     println!("| metric | value |     | change |");
     println!("| ------ | ----: | :-: | :----: |");
     for comparison in comparisons {
-        let trend = if let Some(absolute_change) = comparison.absolute_change {
-            if absolute_change.is_positive() {
-                ":red_circle:"
-            } else if absolute_change.is_negative() {
-                ":white_check_mark:"
-            } else {
-                ":white_circle:"
-            }
-        } else {
-            ""
+        let trend = match comparison.trend() {
+            Some(Trend::Worse) => ":red_circle:",
+            Some(Trend::Better) => ":white_check_mark:",
+            Some(Trend::Unchanged) => ":white_circle:",
+            None => "",
         };
-        print!("| {} | ", comparison.metric);
+        print!("| {} | ", escape(comparison.name()));
         if let Some(new_value) = comparison.new_value {
             print!("{new_value}");
+        } else if let Some(old_value) = comparison.old_value {
+            print!("~~{old_value}~~");
         } else {
             print!("-");
         }
         print!(" | {trend} | ");
-        let change = comparison.absolute_change.zip(comparison.relative_change);
-        if let Some((absolute_change, relative_change)) = change {
-            print!("{absolute_change} ({relative_change})");
+        if let Some(absolute_change) = comparison.absolute_change {
+            print!("{absolute_change}");
+            if let Some(relative_change) = comparison.relative_change {
+                print!(" ({relative_change})");
+            }
         }
         println!(" |");
     }
 }
+
+/// Escapes text so that it can be used in a table cell.
+fn escape(s: &str) -> String {
+    s.trim()
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_table_cell() {
+        assert_eq!(escape("a|b"), "a\\|b");
+        assert_eq!(
+            escape("error: failed\r\ncaused by:\n  b\n"),
+            "error: failed<br>caused by:<br>  b"
+        );
+    }
+}
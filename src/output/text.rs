@@ -1,64 +1,104 @@
 use anstream::{print, println};
 use anstyle::{AnsiColor, Color, Style};
 
-use crate::data::{Comparison, Comparisons};
+use crate::data::{Comparison, Comparisons, Trend};
 
+const STYLE_GROUP: Style = Style::new().bold().underline();
 const STYLE_METRIC: Style = Style::new().bold();
 const STYLE_CHANGE_NONE: Style = Style::new().dimmed();
 const STYLE_CHANGE_BETTER: Style = Color::Ansi(AnsiColor::Green).on_default();
 const STYLE_CHANGE_WORSE: Style = Color::Ansi(AnsiColor::Red).on_default();
 
-pub fn print_comparisons(comparisons: &Comparisons) {
-    if comparisons.significant.is_empty() {
+pub fn print_comparisons(baseline: Option<&str>, comparisons: &Comparisons) {
+    if let Some(baseline) = baseline {
+        println!("Baseline: {baseline}");
+        println!();
+    }
+    if comparisons.is_unchanged() {
         println!("No significant changes.");
+    } else if comparisons.significant.is_empty() {
+        println!("No significant value changes.");
     } else {
         println!("Significant changes:");
         print_comparison_table(&comparisons.significant);
     }
 
-    if !comparisons.insignificant.is_empty() {
+    if !comparisons.failed.is_empty() {
         println!();
-        println!("Insignificant changes:");
-        print_comparison_table(&comparisons.insignificant);
+        println!("Failed metrics:");
+        for failure in &comparisons.failed {
+            for (values, error) in [("baseline", &failure.baseline), ("test", &failure.test)] {
+                if let Some(error) = error {
+                    println!(
+                        "{STYLE_METRIC}{}{STYLE_METRIC:#}\t{values}: {STYLE_CHANGE_WORSE}{error}{STYLE_CHANGE_WORSE:#}",
+                        failure.metric
+                    );
+                }
+            }
+        }
+    }
+
+    let sections = [
+        ("Added metrics", &comparisons.added),
+        ("Removed metrics", &comparisons.removed),
+        ("Changed metric definitions", &comparisons.stale),
+        ("Insignificant changes", &comparisons.insignificant),
+        ("Unchanged metrics", &comparisons.unchanged),
+    ];
+    for (title, comparisons) in sections {
+        if !comparisons.is_empty() {
+            println!();
+            println!("{title}:");
+            print_comparison_table(comparisons);
+        }
     }
 }
 
 fn print_comparison_table(comparisons: &[Comparison]) {
-    for comparison in comparisons {
-        let mut style_change = Style::new();
-        if let Some(absolute_change) = comparison.absolute_change {
-            if absolute_change.is_positive() {
-                style_change = STYLE_CHANGE_WORSE;
-            } else if absolute_change.is_negative() {
-                style_change = STYLE_CHANGE_BETTER;
-            } else {
-                style_change = STYLE_CHANGE_NONE;
-            }
-        }
-        print!("{STYLE_METRIC}{}{STYLE_METRIC:#}\t", comparison.metric);
-        if let Some(old_value) = comparison.old_value {
-            print!("{}", old_value);
-        } else {
-            print!("-");
-        }
-        print!("\t");
-        if let Some(new_value) = comparison.new_value {
-            print!("{}", new_value);
-        } else {
-            print!("-");
-        }
-        print!("\t");
-        if let Some(absolute_change) = comparison.absolute_change {
-            print!("{style_change}{absolute_change}{style_change:#}");
+    for (group, comparisons) in super::groups(comparisons) {
+        let indent = if let Some(group) = group {
+            println!("{STYLE_GROUP}{group}{STYLE_GROUP:#}");
+            "  "
         } else {
-            print!("-");
+            ""
+        };
+        for comparison in comparisons {
+            print!("{indent}");
+            print_comparison(comparison);
         }
-        print!("\t");
-        if let Some(relative_change) = comparison.relative_change {
-            print!("{style_change}{relative_change}{style_change:#}");
-        } else {
-            print!("-");
-        }
-        println!();
     }
 }
+
+fn print_comparison(comparison: &Comparison) {
+    let style_change = match comparison.trend() {
+        Some(Trend::Worse) => STYLE_CHANGE_WORSE,
+        Some(Trend::Better) => STYLE_CHANGE_BETTER,
+        Some(Trend::Unchanged) => STYLE_CHANGE_NONE,
+        None => Style::new(),
+    };
+    print!("{STYLE_METRIC}{}{STYLE_METRIC:#}\t", comparison.name());
+    if let Some(old_value) = comparison.old_value {
+        print!("{}", old_value);
+    } else {
+        print!("-");
+    }
+    print!("\t");
+    if let Some(new_value) = comparison.new_value {
+        print!("{}", new_value);
+    } else {
+        print!("-");
+    }
+    print!("\t");
+    if let Some(absolute_change) = comparison.absolute_change {
+        print!("{style_change}{absolute_change}{style_change:#}");
+    } else {
+        print!("-");
+    }
+    print!("\t");
+    if let Some(relative_change) = comparison.relative_change {
+        print!("{style_change}{relative_change}{style_change:#}");
+    } else {
+        print!("-");
+    }
+    println!();
+}
@@ -0,0 +1,40 @@
+use std::{io, path::Path, process::Command};
+
+use anyhow::{Context as _, Result};
+use log::info;
+
+use crate::config::PrepareConfig;
+
+/// Runs the prepare commands in order and fails on the first command that is not successful.
+pub fn run(config: &PrepareConfig, root: &Path) -> Result<()> {
+    for prepare in &config.commands {
+        let command_line = prepare.command.join(" ");
+        let (program, args) = prepare
+            .command
+            .split_first()
+            .context("prepare command must not be empty")?;
+        let dir = if let Some(dir) = &prepare.dir {
+            root.join(dir)
+        } else {
+            root.to_owned()
+        };
+        info!(
+            "Running prepare command `{command_line}` in '{}'",
+            dir.display()
+        );
+        let status = Command::new(program)
+            .args(args)
+            .current_dir(&dir)
+            .envs(&prepare.env)
+            // stdout is reserved for the generated values
+            .stdout(io::stderr())
+            .status()
+            .with_context(|| format!("failed to run prepare command `{command_line}`"))?;
+        anyhow::ensure!(
+            status.success(),
+            "prepare command `{command_line}` failed with status code {status} in '{}'",
+            dir.display()
+        );
+    }
+    Ok(())
+}
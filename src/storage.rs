@@ -0,0 +1,279 @@
+use std::path::Path;
+
+use anyhow::{Context as _, Result};
+use log::{debug, error, info};
+
+use crate::{
+    cache,
+    config::{Config, GitlabConfig, StorageConfig},
+    directory::Directory,
+    http::Http,
+    notes,
+    s3::S3,
+};
+
+/// A storage backend for metric values, identified by the commit they were generated for.
+pub trait Backend {
+    fn name(&self) -> String;
+
+    fn load(&self, commit: &str) -> Result<Option<Loaded>>;
+
+    fn store(&self, commit: &str, data: &str) -> Result<()>;
+
+    fn list(&self) -> Result<Vec<String>>;
+
+    fn can_store(&self) -> bool {
+        true
+    }
+
+    /// Returns true if values loaded from other backends should be stored in this backend.
+    fn is_cache(&self) -> bool {
+        false
+    }
+}
+
+/// Values loaded from a storage backend.
+pub struct Loaded {
+    pub data: String,
+    /// Details about the origin of the values, for example the CI job that generated them.
+    pub source: Option<String>,
+}
+
+impl From<String> for Loaded {
+    fn from(data: String) -> Self {
+        Self { data, source: None }
+    }
+}
+
+/// An ordered chain of storage backends.
+pub struct Storage(Vec<Box<dyn Backend>>);
+
+impl Storage {
+    pub fn new(config: &Config, gitlab: Option<GitlabConfig>, root: &Path) -> Result<Self> {
+        let gitlab = gitlab.or_else(|| config.gitlab.clone());
+        let mut default = vec![StorageConfig::Cache, StorageConfig::Notes];
+        if config.github.is_some() {
+            default.push(StorageConfig::Github);
+        }
+        if gitlab.is_some() {
+            default.push(StorageConfig::Gitlab);
+        }
+        let backends = config.storage.as_ref().unwrap_or(&default);
+        backends
+            .iter()
+            .map(|backend| -> Result<Box<dyn Backend>> {
+                match backend {
+                    StorageConfig::Cache => Ok(Box::new(cache::Cache::new(root))),
+                    StorageConfig::Directory(config) => Ok(Box::new(Directory::new(config))),
+                    StorageConfig::Notes => Ok(Box::new(notes::Notes::new(root))),
+                    StorageConfig::Github => config
+                        .github
+                        .clone()
+                        .context("GitHub storage backend configured but GitHub is not configured")
+                        .map(|github| Box::new(github) as _),
+                    StorageConfig::Gitlab => gitlab
+                        .clone()
+                        .context("Gitlab storage backend configured but Gitlab is not configured")
+                        .map(|gitlab| Box::new(gitlab) as _),
+                    StorageConfig::Http(config) => Ok(Box::new(Http::new(config))),
+                    StorageConfig::S3(config) => Ok(Box::new(S3::new(config))),
+                    #[cfg(feature = "sqlite")]
+                    StorageConfig::Sqlite(config) => {
+                        Ok(Box::new(crate::history::Database::new(&config.path, root)))
+                    }
+                }
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    pub fn backends(&self) -> impl Iterator<Item = &dyn Backend> {
+        self.0.iter().map(AsRef::as_ref)
+    }
+
+    /// Loads the values for a commit from the first backend that has them and stores them in all
+    /// caches before that backend.
+    pub fn load(&self, commit: &str) -> Result<Loaded> {
+        let mut errors = Vec::new();
+        self.find(commit, &mut errors)
+            .ok_or_else(|| self.not_found(&format!("commit {commit}"), errors))
+    }
+
+    /// Loads the values for the first of the given commits that has values in any backend and
+    /// returns that commit together with the values.
+    pub fn load_first(&self, commits: &[String]) -> Result<(String, Loaded)> {
+        let mut errors = Vec::new();
+        for commit in commits {
+            if let Some(values) = self.find(commit, &mut errors) {
+                return Ok((commit.clone(), values));
+            }
+            debug!("No values for commit {commit} in any storage backend");
+        }
+        Err(self.not_found(&format!("commits {}", commits.join(", ")), errors))
+    }
+
+    /// Returns the error for missing values, caused by the errors of the backends that failed.
+    fn not_found(&self, commits: &str, errors: Vec<String>) -> anyhow::Error {
+        let message = format!(
+            "no values found for {commits} in storage backends: {}",
+            self.names()
+        );
+        if errors.is_empty() {
+            anyhow::Error::msg(message)
+        } else {
+            anyhow::Error::msg(errors.join("\n")).context(message)
+        }
+    }
+
+    /// Loads the values for a commit and adds the errors of the backends that failed to `errors`.
+    fn find(&self, commit: &str, errors: &mut Vec<String>) -> Option<Loaded> {
+        for (i, backend) in self.0.iter().enumerate() {
+            // values for dirty working trees are only stored in caches
+            if cache::split_key(commit).1.is_some() && !backend.is_cache() {
+                continue;
+            }
+            match backend.load(commit) {
+                Ok(Some(values)) => {
+                    for cache in self.0[..i].iter().filter(|backend| backend.is_cache()) {
+                        if let Err(err) = cache.store(commit, &values.data) {
+                            error!("Failed to cache downloaded metrics: {:#}", err);
+                        }
+                    }
+                    return Some(values);
+                }
+                Ok(None) => debug!("No values for commit {commit} in {}", backend.name()),
+                Err(err) => {
+                    debug!(
+                        "Failed to load values for commit {commit} from {}: {:#}",
+                        backend.name(),
+                        err
+                    );
+                    let error = format!("{}: {:#}", backend.name(), err);
+                    if !errors.contains(&error) {
+                        errors.push(error);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn names(&self) -> String {
+        let names: Vec<_> = self.0.iter().map(|backend| backend.name()).collect();
+        names.join(", ")
+    }
+
+    /// Stores the values for the current commit in all backends that support storing, except for
+    /// caches if `skip_caches` is set because the values have already been cached.
+    pub fn store(&self, root: &Path, data: &str, skip_caches: bool) -> Result<()> {
+        let Some(commit) = cache::current_commit(root)? else {
+            return Ok(());
+        };
+        let backends = self
+            .0
+            .iter()
+            .filter(|backend| backend.can_store() && !(skip_caches && backend.is_cache()));
+        for backend in backends {
+            info!("Storing values for commit {commit} in {}", backend.name());
+            if let Err(err) = backend.store(&commit, data) {
+                error!(
+                    "Failed to store generated metrics in {}: {:#}",
+                    backend.name(),
+                    err
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    struct Failing;
+
+    impl Backend for Failing {
+        fn name(&self) -> String {
+            "failing".to_owned()
+        }
+
+        fn load(&self, _commit: &str) -> Result<Option<Loaded>> {
+            anyhow::bail!("missing token")
+        }
+
+        fn store(&self, _commit: &str, _data: &str) -> Result<()> {
+            anyhow::bail!("missing token")
+        }
+
+        fn list(&self) -> Result<Vec<String>> {
+            anyhow::bail!("missing token")
+        }
+    }
+
+    /// A backend that records the commits stored in it.
+    struct Recording {
+        cache: bool,
+        stored: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Backend for Recording {
+        fn name(&self) -> String {
+            "recording".to_owned()
+        }
+
+        fn load(&self, _commit: &str) -> Result<Option<Loaded>> {
+            Ok(None)
+        }
+
+        fn store(&self, commit: &str, _data: &str) -> Result<()> {
+            self.stored.borrow_mut().push(commit.to_owned());
+            Ok(())
+        }
+
+        fn list(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn is_cache(&self) -> bool {
+            self.cache
+        }
+    }
+
+    #[test]
+    fn store_skip_caches() {
+        let (dir, commits) = crate::test_util::test_repo(&[0]);
+        let cached = Rc::new(RefCell::new(Vec::new()));
+        let stored = Rc::new(RefCell::new(Vec::new()));
+        let storage = Storage(vec![
+            Box::new(Recording {
+                cache: true,
+                stored: cached.clone(),
+            }),
+            Box::new(Recording {
+                cache: false,
+                stored: stored.clone(),
+            }),
+        ]);
+        storage.store(dir.path(), "", true).unwrap();
+        assert!(cached.borrow().is_empty());
+        assert_eq!(*stored.borrow(), commits);
+        storage.store(dir.path(), "", false).unwrap();
+        assert_eq!(*cached.borrow(), commits);
+    }
+
+    #[test]
+    fn load_errors() {
+        let storage = Storage(vec![Box::new(Failing)]);
+        let err = storage.load("c1").err().unwrap();
+        assert_eq!(
+            format!("{err:#}"),
+            "no values found for commit c1 in storage backends: failing: failing: missing token"
+        );
+        let commits = ["c1".to_owned(), "c2".to_owned()];
+        let err = storage.load_first(&commits).err().unwrap();
+        assert_eq!(err.root_cause().to_string(), "failing: missing token");
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use git2::{build::CheckoutBuilder, Oid, Repository, Signature, Time};
+use tempfile::TempDir;
+
+/// Creates a repository with one commit per commit time on the default branch, starting with the
+/// oldest commit.
+pub fn test_repo(times: &[i64]) -> (TempDir, Vec<String>) {
+    let dir = tempfile::tempdir().unwrap();
+    Repository::init(dir.path()).unwrap();
+    let mut commits: Vec<String> = Vec::new();
+    for time in times {
+        let parents: Vec<_> = commits.last().map(String::as_str).into_iter().collect();
+        commits.push(commit(dir.path(), Some("HEAD"), &parents, *time, &[]));
+    }
+    (dir, commits)
+}
+
+/// Creates a commit that adds the files to the tree of the first parent and updates the
+/// reference.
+///
+/// If the reference is `HEAD`, the commit is also checked out.
+pub fn commit(
+    path: &Path,
+    reference: Option<&str>,
+    parents: &[&str],
+    time: i64,
+    files: &[(&str, &str)],
+) -> String {
+    let repo = Repository::open(path).unwrap();
+    let parents: Vec<_> = parents
+        .iter()
+        .map(|parent| repo.find_commit(Oid::from_str(parent).unwrap()).unwrap())
+        .collect();
+    let base = parents.first().map(|parent| parent.tree().unwrap());
+    let mut builder = repo.treebuilder(base.as_ref()).unwrap();
+    for (name, content) in files {
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        builder.insert(name, blob, 0o100644).unwrap();
+    }
+    let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let signature = Signature::new("test", "test@localhost", &Time::new(time, 0)).unwrap();
+    let parents: Vec<_> = parents.iter().collect();
+    let oid = repo
+        .commit(reference, &signature, &signature, "test", &tree, &parents)
+        .unwrap();
+    if reference == Some("HEAD") {
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+    }
+    oid.to_string()
+}